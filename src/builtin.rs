@@ -1,11 +1,21 @@
 use crate::core;
 use crate::types;
+use crate::util;
+
+// Integer results longer than this many bits signal `overflow-error` rather
+// than exhausting memory.
+const MAX_INTEGER_BITS: usize = 1 << 24;
+
+thread_local! {
+    static RANDOM: std::cell::RefCell<util::Random> =
+        std::cell::RefCell::new(util::Random::from_time());
+}
 
 macro_rules! defun {
     ($name: ident, $arg: ident, $env: ident, _, $body: block) => {
         pub fn $name(
             $arg: &types::RuspExp,
-            $env: &mut types::RuspEnv,
+            $env: &types::RuspEnv,
         ) -> anyhow::Result<types::RuspExp> {
             types::extract_args!($arg, $env, _, $body)
         }
@@ -13,15 +23,79 @@ macro_rules! defun {
     ($name: ident, $arg: ident, $env: ident, $arglist: tt, $body: block) => {
         pub fn $name(
             $arg: &types::RuspExp,
-            $env: &mut types::RuspEnv,
+            $env: &types::RuspEnv,
         ) -> anyhow::Result<types::RuspExp> {
             types::extract_args!($arg, $env, $arglist, $body)
         }
     };
 }
 
+macro_rules! defspecial {
+    ($name: ident, $arg: ident, $env: ident, _, $body: block) => {
+        pub fn $name(
+            $arg: &types::RuspExp,
+            $env: &types::RuspEnv,
+        ) -> anyhow::Result<types::RuspTail> {
+            types::extract_args!($arg, $env, _, $body)
+        }
+    };
+    ($name: ident, $arg: ident, $env: ident, $arglist: tt, $body: block) => {
+        pub fn $name(
+            $arg: &types::RuspExp,
+            $env: &types::RuspEnv,
+        ) -> anyhow::Result<types::RuspTail> {
+            types::extract_args!($arg, $env, $arglist, $body)
+        }
+    };
+}
+
+// Apply an exact operation in `i64` when both operands are fixnums and it
+// does not overflow, and on rationals otherwise. `RuspExp::rational` demotes
+// results that are integers, or fit in a fixnum, again.
+fn exact_op(
+    a: &types::RuspExp,
+    b: &types::RuspExp,
+    checked_fn: fn(i64, i64) -> Option<i64>,
+    ratio_fn: fn(dashu_ratio::RBig, dashu_ratio::RBig) -> dashu_ratio::RBig,
+) -> anyhow::Result<types::RuspExp> {
+    if let (
+        types::RuspExp::Atom(types::RuspAtom::Int(a)),
+        types::RuspExp::Atom(types::RuspAtom::Int(b)),
+    ) = (a, b)
+    {
+        if let Some(i) = checked_fn(*a, *b) {
+            return Ok(types::RuspExp::Atom(types::RuspAtom::Int(i)));
+        }
+    }
+    Ok(types::RuspExp::rational(ratio_fn(
+        a.as_rational().unwrap(),
+        b.as_rational().unwrap(),
+    )))
+}
+
+// Exact division gives a ratio unless the quotient is an integer; only a
+// zero divisor is an error, as `most-negative-fixnum` divided by -1 simply
+// becomes a bignum.
+fn exact_divide(a: &types::RuspExp, b: &types::RuspExp) -> anyhow::Result<types::RuspExp> {
+    anyhow::ensure!(
+        b != &types::RuspExp::Atom(types::RuspAtom::Int(0)),
+        types::RuspErr::ArithError
+    );
+    exact_op(
+        a,
+        b,
+        |a, b| match a.checked_rem(b) {
+            Some(0) => a.checked_div(b),
+            _ => None,
+        },
+        |a, b| a / b,
+    )
+}
+
+// Numbers are promoted along int -> ratio -> float; `$exact_fn` combines
+// two rationals and `$fn` two floats, following IEEE rules.
 macro_rules! basic_op {
-    ($arg: ident, $env: ident, $fn: expr, $init: expr, $first_init_p: expr) => {{
+    ($arg: ident, $env: ident, $fn: expr, $exact_fn: expr, $init: expr, $first_init_p: expr) => {{
         let arg_lst = $arg.into_iter().collect::<Result<Vec<_>, _>>()?;
 
         let lst = arg_lst
@@ -29,57 +103,38 @@ macro_rules! basic_op {
             .map(|x| core::eval(x, $env))
             .collect::<Result<Vec<_>, _>>()?;
 
-        anyhow::ensure!(
-            lst.iter().all(|x| x.numberp()),
-            types::RuspErr::WrongTypeArgument {
-                expected: "list<number>".into(),
-                actual: format!(
-                    "({})",
-                    lst.iter()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                )
-                .into()
-            }
-        );
+        if let Some(x) = lst.iter().find(|x| !x.numberp()) {
+            anyhow::bail!(types::RuspErr::WrongTypeArgument {
+                expected: "number-or-marker-p".into(),
+                actual: x.clone()
+            });
+        }
 
         if lst.len() == 0 {
             return Ok(types::RuspExp::Atom(types::RuspAtom::Int($init)));
         }
 
         let floatp = lst.iter().any(|x| x.floatp());
-        let mut init: f64 = $init as f64;
         let mut iter = lst.into_iter();
-
-        if $first_init_p {
-            match iter.next().unwrap() {
-                types::RuspExp::Atom(types::RuspAtom::Int(x)) => init = x as f64,
-                types::RuspExp::Atom(types::RuspAtom::Float(x)) => init = x,
-                _ => unreachable!(),
-            };
-        }
+        let first = if $first_init_p {
+            iter.next().unwrap()
+        } else {
+            types::RuspExp::Atom(types::RuspAtom::Int($init))
+        };
 
         if floatp {
-            let mut acc: f64 = init as f64;
+            let mut acc: f64 = first.as_float().unwrap();
             for x in iter {
-                match x {
-                    types::RuspExp::Atom(types::RuspAtom::Int(i)) => acc = $fn(acc, i as f64),
-                    types::RuspExp::Atom(types::RuspAtom::Float(f)) => acc = $fn(acc, f),
-                    _ => unreachable!(),
-                }
+                acc = $fn(acc, x.as_float().unwrap());
             }
             return Ok(types::RuspExp::Atom(types::RuspAtom::Float(acc)));
         }
 
-        let mut acc: i64 = init as i64;
+        let mut acc = first;
         for x in iter {
-            match x {
-                types::RuspExp::Atom(types::RuspAtom::Int(i)) => acc = $fn(acc, i),
-                _ => unreachable!(),
-            }
+            acc = $exact_fn(&acc, &x)?;
         }
-        Ok(types::RuspExp::Atom(types::RuspAtom::Int(acc)))
+        Ok(acc)
     }};
 }
 
@@ -92,76 +147,22 @@ macro_rules! basic_pred {
             .map(|x| core::eval(x, $env))
             .collect::<Result<Vec<_>, _>>()?;
 
-        anyhow::ensure!(
-            lst.iter().all(|x| x.numberp()),
-            types::RuspErr::WrongTypeArgument {
-                expected: "list<number>".into(),
-                actual: format!(
-                    "({})",
-                    lst.iter()
-                        .map(|e| e.to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ")
-                )
-                .into()
-            }
-        );
-
-        if lst.len() <= 1 {
-            return Ok(types::t!());
+        if let Some(x) = lst.iter().find(|x| !x.numberp()) {
+            anyhow::bail!(types::RuspErr::WrongTypeArgument {
+                expected: "number-or-marker-p".into(),
+                actual: x.clone()
+            });
         }
 
         let floatp = lst.iter().any(|x| x.floatp());
-        let mut iter = lst.into_iter();
-        let mut res: bool = true;
-
-        if floatp {
-            let mut last_value: f64 = match iter.next().unwrap() {
-                types::RuspExp::Atom(types::RuspAtom::Int(i)) => i as f64,
-                types::RuspExp::Atom(types::RuspAtom::Float(f)) => f,
-                _ => unreachable!(),
-            };
-            for x in iter {
-                match x {
-                    types::RuspExp::Atom(types::RuspAtom::Int(i)) => {
-                        if !$fn(last_value, i as f64) {
-                            res = false;
-                            break;
-                        }
-                        last_value = i as f64;
-                    }
-                    types::RuspExp::Atom(types::RuspAtom::Float(f)) => {
-                        if !$fn(last_value, f) {
-                            res = false;
-                            break;
-                        }
-                        last_value = f;
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            if res {
-                return Ok(types::t!());
-            }
-            return Ok(types::nil!());
-        }
-
-        let mut last_value: i64 = match iter.next().unwrap() {
-            types::RuspExp::Atom(types::RuspAtom::Int(i)) => i,
-            _ => unreachable!(),
-        };
-        for x in iter {
-            match x {
-                types::RuspExp::Atom(types::RuspAtom::Int(i)) => {
-                    if !$fn(last_value, i) {
-                        res = false;
-                        break;
-                    }
-                    last_value = i;
-                }
-                _ => unreachable!(),
-            }
-        }
+        let res = lst.windows(2).all(|pair| match (&pair[0], &pair[1]) {
+            _ if floatp => $fn(pair[0].as_float().unwrap(), pair[1].as_float().unwrap()),
+            (
+                types::RuspExp::Atom(types::RuspAtom::Int(a)),
+                types::RuspExp::Atom(types::RuspAtom::Int(b)),
+            ) => $fn(a, b),
+            (a, b) => $fn(a.as_rational().unwrap(), b.as_rational().unwrap()),
+        });
         if res {
             return Ok(types::t!());
         }
@@ -170,19 +171,40 @@ macro_rules! basic_pred {
 }
 
 defun!(arith_plus, arg, env, _, {
-    basic_op!(arg, env, |acc, x| acc + x, 0, false)
+    basic_op!(
+        arg,
+        env,
+        |acc, x| acc + x,
+        |a, b| exact_op(a, b, i64::checked_add, |a, b| a + b),
+        0,
+        false
+    )
 });
 
 defun!(arith_minus, arg, env, _, {
-    basic_op!(arg, env, |acc, x| acc - x, 0, true)
+    basic_op!(
+        arg,
+        env,
+        |acc, x| acc - x,
+        |a, b| exact_op(a, b, i64::checked_sub, |a, b| a - b),
+        0,
+        true
+    )
 });
 
 defun!(arith_multiply, arg, env, _, {
-    basic_op!(arg, env, |acc, x| acc * x, 1, false)
+    basic_op!(
+        arg,
+        env,
+        |acc, x| acc * x,
+        |a, b| exact_op(a, b, i64::checked_mul, |a, b| a * b),
+        1,
+        false
+    )
 });
 
 defun!(arith_divide, arg, env, _, {
-    basic_op!(arg, env, |acc, x| acc / x, 1, true)
+    basic_op!(arg, env, |acc, x| acc / x, exact_divide, 1, true)
 });
 
 defun!(arith_eq, arg, env, _, {
@@ -209,54 +231,2619 @@ defun!(arith_gte, arg, env, _, {
     basic_pred!(arg, env, |acc, x| acc >= x)
 });
 
-defun!(if_, arg, env, (cond, then, &optional else_), {
+fn rational_arg(x: &types::RuspExp) -> anyhow::Result<dashu_ratio::RBig> {
+    x.as_rational().ok_or_else(|| {
+        anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "rationalp".into(),
+            actual: x.clone()
+        })
+    })
+}
+
+defun!(numerator, arg, env, (x), {
+    let r = rational_arg(&core::eval(x, env)?)?;
+    Ok(types::RuspExp::integer(r.numerator().clone()))
+});
+
+defun!(denominator, arg, env, (x), {
+    let r = rational_arg(&core::eval(x, env)?)?;
+    Ok(types::RuspExp::integer(r.denominator().clone().into()))
+});
+
+// Divide `number` by `divisor`, or by 1 when it is nil, and return the
+// quotient rounded to an integer by `round_fn` along with the remainder.
+// The remainder is a float when either argument is.
+fn rounding_impl(
+    number: &types::RuspExp,
+    divisor: &types::RuspExp,
+    env: &types::RuspEnv,
+    round_fn: fn(&dashu_ratio::RBig) -> dashu_int::IBig,
+) -> anyhow::Result<types::RuspExp> {
+    let number = core::eval(number, env)?;
+    let divisor = match core::eval(divisor, env)? {
+        x if x.nilp() => types::RuspExp::Atom(types::RuspAtom::Int(1)),
+        x => x,
+    };
+    if let Some(x) = [&number, &divisor].into_iter().find(|x| !x.numberp()) {
+        anyhow::bail!(types::RuspErr::WrongTypeArgument {
+            expected: "number-or-marker-p".into(),
+            actual: x.clone()
+        });
+    }
+
+    // floats convert exactly, except for infinities and NaN
+    let exact = |x: &types::RuspExp| match x {
+        types::RuspExp::Atom(types::RuspAtom::Float(f)) => {
+            dashu_ratio::RBig::try_from(*f).map_err(|_| anyhow::anyhow!(types::RuspErr::ArithError))
+        }
+        _ => Ok(x.as_rational().unwrap()),
+    };
+    let n = exact(&number)?;
+    let d = exact(&divisor)?;
+    anyhow::ensure!(!d.is_zero(), types::RuspErr::ArithError);
+
+    let q = round_fn(&(n.clone() / d.clone()));
+    let r = n - dashu_ratio::RBig::from(q.clone()) * d;
+    let r = if number.floatp() || divisor.floatp() {
+        types::RuspExp::Atom(types::RuspAtom::Float(r.to_f64().value()))
+    } else {
+        types::RuspExp::rational(r)
+    };
+    Ok(core::values(vec![types::RuspExp::integer(q), r]))
+}
+
+// Round to the nearest integer, and to the even one on ties.
+fn round_half_even(x: &dashu_ratio::RBig) -> dashu_int::IBig {
+    let floor = x.floor();
+    let fract = x.clone() - dashu_ratio::RBig::from(floor.clone());
+    let half = dashu_ratio::RBig::from_parts(1.into(), 2u8.into());
+    match fract.cmp(&half) {
+        std::cmp::Ordering::Less => floor,
+        std::cmp::Ordering::Greater => floor + 1,
+        std::cmp::Ordering::Equal if (&floor % 2) == 0 => floor,
+        std::cmp::Ordering::Equal => floor + 1,
+    }
+}
+
+defun!(floor, arg, env, (number, &optional divisor), {
+    rounding_impl(number, divisor, env, dashu_ratio::RBig::floor)
+});
+
+defun!(ceiling, arg, env, (number, &optional divisor), {
+    rounding_impl(number, divisor, env, dashu_ratio::RBig::ceil)
+});
+
+defun!(round, arg, env, (number, &optional divisor), {
+    rounding_impl(number, divisor, env, round_half_even)
+});
+
+defun!(truncate, arg, env, (number, &optional divisor), {
+    rounding_impl(number, divisor, env, dashu_ratio::RBig::trunc)
+});
+
+fn number_arg(x: types::RuspExp) -> anyhow::Result<types::RuspExp> {
+    anyhow::ensure!(
+        x.numberp(),
+        types::RuspErr::WrongTypeArgument {
+            expected: "number-or-marker-p".into(),
+            actual: x.clone()
+        }
+    );
+    Ok(x)
+}
+
+fn float_arg(x: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<f64> {
+    Ok(number_arg(core::eval(x, env)?)?.as_float().unwrap())
+}
+
+fn integer_arg(x: types::RuspExp) -> anyhow::Result<dashu_int::IBig> {
+    x.as_bigint().ok_or_else(|| {
+        anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "integer-or-marker-p".into(),
+            actual: x.clone()
+        })
+    })
+}
+
+fn float_exp(f: f64) -> types::RuspExp {
+    types::RuspExp::Atom(types::RuspAtom::Float(f))
+}
+
+// The remainder of an integer division, with the sign of the dividend.
+defun!(rem, arg, env, (x, y), {
+    let a = integer_arg(core::eval(x, env)?)?;
+    let b = integer_arg(core::eval(y, env)?)?;
+    anyhow::ensure!(!b.is_zero(), types::RuspErr::ArithError);
+    Ok(types::RuspExp::integer(a % b))
+});
+
+// The remainder of a division rounded down, with the sign of the divisor.
+defun!(mod_, arg, env, (x, y), {
+    let a = number_arg(core::eval(x, env)?)?;
+    let b = number_arg(core::eval(y, env)?)?;
+    if a.floatp() || b.floatp() {
+        let b = b.as_float().unwrap();
+        let r = a.as_float().unwrap() % b;
+        if r != 0.0 && (r < 0.0) != (b < 0.0) {
+            return Ok(float_exp(r + b));
+        }
+        return Ok(float_exp(r));
+    }
+
+    let a = a.as_rational().unwrap();
+    let b = b.as_rational().unwrap();
+    anyhow::ensure!(!b.is_zero(), types::RuspErr::ArithError);
+    let q = (a.clone() / b.clone()).floor();
+    Ok(types::RuspExp::rational(a - dashu_ratio::RBig::from(q) * b))
+});
+
+defun!(one_plus, arg, env, (x), {
+    let x = number_arg(core::eval(x, env)?)?;
+    match x {
+        types::RuspExp::Atom(types::RuspAtom::Float(f)) => Ok(float_exp(f + 1.0)),
+        _ => exact_op(
+            &x,
+            &types::RuspExp::Atom(types::RuspAtom::Int(1)),
+            i64::checked_add,
+            |a, b| a + b,
+        ),
+    }
+});
+
+defun!(one_minus, arg, env, (x), {
+    let x = number_arg(core::eval(x, env)?)?;
+    match x {
+        types::RuspExp::Atom(types::RuspAtom::Float(f)) => Ok(float_exp(f - 1.0)),
+        _ => exact_op(
+            &x,
+            &types::RuspExp::Atom(types::RuspAtom::Int(1)),
+            i64::checked_sub,
+            |a, b| a - b,
+        ),
+    }
+});
+
+defun!(abs, arg, env, (x), {
+    let x = number_arg(core::eval(x, env)?)?;
+    match x {
+        types::RuspExp::Atom(types::RuspAtom::Float(f)) => Ok(float_exp(f.abs())),
+        _ if x.as_rational().unwrap() < dashu_ratio::RBig::ZERO => exact_op(
+            &types::RuspExp::Atom(types::RuspAtom::Int(0)),
+            &x,
+            i64::checked_sub,
+            |a, b| a - b,
+        ),
+        _ => Ok(x),
+    }
+});
+
+// Compare two numbers exactly unless one of them is a float. Only NaN is
+// unordered.
+fn compare_numbers(a: &types::RuspExp, b: &types::RuspExp) -> Option<std::cmp::Ordering> {
+    if a.floatp() || b.floatp() {
+        return a.as_float().unwrap().partial_cmp(&b.as_float().unwrap());
+    }
+    Some(a.as_rational().unwrap().cmp(&b.as_rational().unwrap()))
+}
+
+// The argument that is `ordering` relative to all others, returned as it
+// is; a NaN argument wins over any number.
+fn extremum(
+    first: &types::RuspExp,
+    rest: &types::RuspExp,
+    env: &types::RuspEnv,
+    ordering: std::cmp::Ordering,
+) -> anyhow::Result<types::RuspExp> {
+    let mut acc = number_arg(core::eval(first, env)?)?;
+    for x in rest {
+        let x = number_arg(core::eval(x?, env)?)?;
+        match compare_numbers(&x, &acc) {
+            Some(o) if o == ordering => acc = x,
+            None if x.as_float().unwrap().is_nan() => acc = x,
+            _ => (),
+        }
+    }
+    Ok(acc)
+}
+
+defun!(min, arg, env, (first, &rest rest), {
+    extremum(first, rest, env, std::cmp::Ordering::Less)
+});
+
+defun!(max, arg, env, (first, &rest rest), {
+    extremum(first, rest, env, std::cmp::Ordering::Greater)
+});
+
+// The number of bits in the magnitude of `x`.
+fn bit_len(x: &dashu_int::IBig) -> usize {
+    dashu_int::ops::BitTest::bit_len(&dashu_int::ops::UnsignedAbs::unsigned_abs(x.clone()))
+}
+
+// A rational raised to an integer power stays exact; anything else is
+// computed in floating point.
+defun!(expt, arg, env, (base, power), {
+    let base = number_arg(core::eval(base, env)?)?;
+    let power = number_arg(core::eval(power, env)?)?;
+    if base.rationalp() && power.intp() {
+        let n = power.as_int()?;
+        let base = base.as_rational().unwrap();
+        // a part of magnitude 2 or more grows by at least a bit per factor
+        for part in [base.numerator().clone(), base.denominator().clone().into()] {
+            let bits = (bit_len(&part).saturating_sub(1) as u64).saturating_mul(n.unsigned_abs());
+            anyhow::ensure!(
+                bits <= MAX_INTEGER_BITS as u64,
+                types::RuspErr::OverflowError
+            );
+        }
+        let r = base.pow(n.unsigned_abs() as usize);
+        if n >= 0 {
+            return Ok(types::RuspExp::rational(r));
+        }
+        anyhow::ensure!(!r.is_zero(), types::RuspErr::ArithError);
+        return Ok(types::RuspExp::rational(dashu_ratio::RBig::ONE / r));
+    }
+    Ok(float_exp(
+        base.as_float().unwrap().powf(power.as_float().unwrap()),
+    ))
+});
+
+defun!(sqrt, arg, env, (x), {
+    Ok(float_exp(float_arg(x, env)?.sqrt()))
+});
+
+defun!(exp, arg, env, (x), {
+    Ok(float_exp(float_arg(x, env)?.exp()))
+});
+
+defun!(log, arg, env, (x, &optional base), {
+    let x = float_arg(x, env)?;
+    if core::eval(base, env)?.nilp() {
+        return Ok(float_exp(x.ln()));
+    }
+    let base = float_arg(base, env)?;
+    // the dedicated functions are exact for powers of their base
+    let res = if base == 2.0 {
+        x.log2()
+    } else if base == 10.0 {
+        x.log10()
+    } else {
+        x.ln() / base.ln()
+    };
+    Ok(float_exp(res))
+});
+
+defun!(sin, arg, env, (x), {
+    Ok(float_exp(float_arg(x, env)?.sin()))
+});
+
+defun!(cos, arg, env, (x), {
+    Ok(float_exp(float_arg(x, env)?.cos()))
+});
+
+defun!(tan, arg, env, (x), {
+    Ok(float_exp(float_arg(x, env)?.tan()))
+});
+
+defun!(atan, arg, env, (y, &optional x), {
+    let y = float_arg(y, env)?;
+    if core::eval(x, env)?.nilp() {
+        return Ok(float_exp(y.atan()));
+    }
+    Ok(float_exp(y.atan2(float_arg(x, env)?)))
+});
+
+// Combine integer arguments with a bitwise operation, starting from `init`.
+fn bitwise_impl(
+    arg: &types::RuspExp,
+    env: &types::RuspEnv,
+    init: i64,
+    op: fn(dashu_int::IBig, dashu_int::IBig) -> dashu_int::IBig,
+) -> anyhow::Result<types::RuspExp> {
+    let mut acc = dashu_int::IBig::from(init);
+    for x in arg {
+        acc = op(acc, integer_arg(core::eval(x?, env)?)?);
+    }
+    Ok(types::RuspExp::integer(acc))
+}
+
+defun!(logand, arg, env, _, {
+    bitwise_impl(arg, env, -1, |a, b| a & b)
+});
+
+defun!(logior, arg, env, _, {
+    bitwise_impl(arg, env, 0, |a, b| a | b)
+});
+
+defun!(logxor, arg, env, _, {
+    bitwise_impl(arg, env, 0, |a, b| a ^ b)
+});
+
+// Shift left by `count` bits, or right when it is negative, rounding down.
+defun!(ash, arg, env, (value, count), {
+    let value = integer_arg(core::eval(value, env)?)?;
+    let count = core::eval(count, env)?.as_int()?;
+    let bits = count.unsigned_abs() as usize;
+    match count >= 0 {
+        true => {
+            anyhow::ensure!(
+                value.is_zero() || bit_len(&value).saturating_add(bits) <= MAX_INTEGER_BITS,
+                types::RuspErr::OverflowError
+            );
+            Ok(types::RuspExp::integer(value << bits))
+        }
+        false => Ok(types::RuspExp::integer(value >> bits)),
+    }
+});
+
+defun!(float, arg, env, (x), { Ok(float_exp(float_arg(x, env)?)) });
+
+// With a positive integer `limit`, return an integer in `[0, limit)`;
+// otherwise any fixnum. `t` reseeds the generator from the clock and a
+// string seeds it reproducibly.
+defun!(random, arg, env, (&optional limit), {
+    let limit = core::eval(limit, env)?;
+    RANDOM.with(|random| {
+        let mut random = random.borrow_mut();
+        match &limit {
+            types::RuspExp::Atom(types::RuspAtom::String(s)) => {
+                *random = util::Random::from_string(s)
+            }
+            x if x == &types::t!() => *random = util::Random::from_time(),
+            x if x.nilp() => (),
+            x => {
+                let limit = x.as_int()?;
+                if limit > 0 {
+                    let i = random.below(limit as u64) as i64;
+                    return Ok(types::RuspExp::Atom(types::RuspAtom::Int(i)));
+                }
+            }
+        }
+        let i = random.next_u64() as i64;
+        Ok(types::RuspExp::Atom(types::RuspAtom::Int(i)))
+    })
+});
+
+defun!(values, arg, env, _, {
+    let values = arg
+        .into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(core::values(values))
+});
+
+defun!(multiple_value_list, arg, env, (form), {
+    Ok(core::eval_values(form, env)?.into_iter().collect())
+});
+
+defspecial!(multiple_value_bind, arg, env, (vars, form, &rest body), {
+    let mut values = core::eval_values(form, env)?.into_iter();
+    let new_env = types::RuspEnv::with_outer(env);
+    for var in &**vars {
+        let sym = var?.as_symbol()?;
+        new_env.define_variable(sym, values.next().unwrap_or(types::nil!()));
+    }
+    core::eval_body_tail(body, &new_env)
+});
+
+defspecial!(if_, arg, env, (cond, then, &rest else_), {
     if core::eval(cond, env)?.non_nil_p() {
-        return core::eval(then, env)
+        return Ok(types::RuspTail::Eval((**then).clone(), env.clone()));
     }
-    core::eval(else_, env)
+    core::eval_body_tail(else_, env)
 });
 
-defun!(set, arg, env, (sym_, val), {
-    let sym = core::eval(sym_, env)?;
-    match sym {
-        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => {
-            let val = core::eval(val, env)?;
-            env.variable.insert(s, val.clone());
-            Ok(val)
+defspecial!(cond, arg, env, _, {
+    for clause_ in arg {
+        let clause = clause_?;
+        let test = core::eval(clause.car()?, env)?;
+        if test.non_nil_p() {
+            let body = clause.cdr()?;
+            if body.nilp() {
+                return Ok(types::RuspTail::Value(test));
+            }
+            return core::eval_body_tail(body, env);
         }
-        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-            expected: "symbol".into(),
-            actual: sym.to_string().into()
-        })),
     }
+    Ok(types::RuspTail::Value(types::nil!()))
 });
 
-defun!(setq, arg, env, (sym, val), {
-    match &**sym {
-        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => {
-            let val = core::eval(val, env)?;
-            env.variable.insert(s.to_string(), val.clone());
-            Ok(val)
+defspecial!(when, arg, env, (cond, &rest body), {
+    if core::eval(cond, env)?.non_nil_p() {
+        return core::eval_body_tail(body, env);
+    }
+    Ok(types::RuspTail::Value(types::nil!()))
+});
+
+defspecial!(unless, arg, env, (cond, &rest body), {
+    if core::eval(cond, env)?.nilp() {
+        return core::eval_body_tail(body, env);
+    }
+    Ok(types::RuspTail::Value(types::nil!()))
+});
+
+defspecial!(and, arg, env, _, {
+    let mut iter = arg.into_iter().peekable();
+    while let Some(x) = iter.next() {
+        let x = x?;
+        if iter.peek().is_none() {
+            return Ok(types::RuspTail::Eval((**x).clone(), env.clone()));
+        }
+        if core::eval(x, env)?.nilp() {
+            return Ok(types::RuspTail::Value(types::nil!()));
+        }
+    }
+    Ok(types::RuspTail::Value(types::t!()))
+});
+
+defspecial!(or, arg, env, _, {
+    let mut iter = arg.into_iter().peekable();
+    while let Some(x) = iter.next() {
+        let x = x?;
+        if iter.peek().is_none() {
+            return Ok(types::RuspTail::Eval((**x).clone(), env.clone()));
+        }
+        let res = core::eval(x, env)?;
+        if res.non_nil_p() {
+            return Ok(types::RuspTail::Value(res));
         }
-        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-            expected: "symbol".into(),
-            actual: sym.to_string().into()
-        })),
     }
+    Ok(types::RuspTail::Value(types::nil!()))
 });
 
-defun!(quote, arg, _env, (exp), { Ok(*exp.clone()) });
+defun!(not, arg, env, (x), {
+    if core::eval(x, env)?.nilp() {
+        return Ok(types::t!());
+    }
+    Ok(types::nil!())
+});
 
-defun!(lambda, arg, _env, (params, body), {
-    Ok(types::RuspExp::Atom(types::RuspAtom::Lambda {
-        params: params.clone(),
-        body: body.clone(),
+defun!(while_, arg, env, (cond, &rest body), {
+    while core::eval(cond, env)?.non_nil_p() {
+        core::eval_body(body, env)?;
+    }
+    Ok(types::nil!())
+});
+
+defun!(dotimes, arg, env, (spec, &rest body), {
+    let var = spec.car()?.as_symbol()?;
+    let count = core::eval(spec.cdr()?.car()?, env)?.as_int()?;
+    let result = spec.cdr()?.cdr()?;
+
+    for i in 0..count {
+        let new_env = types::RuspEnv::with_outer(env);
+        new_env.define_variable(var, types::RuspExp::Atom(types::RuspAtom::Int(i)));
+        core::eval_body(body, &new_env)?;
+    }
+
+    let new_env = types::RuspEnv::with_outer(env);
+    new_env.define_variable(
+        var,
+        types::RuspExp::Atom(types::RuspAtom::Int(count.max(0))),
+    );
+    core::eval_body(result, &new_env)
+});
+
+defun!(dolist, arg, env, (spec, &rest body), {
+    let var = spec.car()?.as_symbol()?;
+    let lst = core::eval(spec.cdr()?.car()?, env)?;
+    let result = spec.cdr()?.cdr()?;
+
+    for x in &lst {
+        let new_env = types::RuspEnv::with_outer(env);
+        new_env.define_variable(var, (**x?).clone());
+        core::eval_body(body, &new_env)?;
+    }
+
+    let new_env = types::RuspEnv::with_outer(env);
+    new_env.define_variable(var, types::nil!());
+    core::eval_body(result, &new_env)
+});
+
+defun!(catch, arg, env, (tag_, &rest body), {
+    let tag = core::eval(tag_, env)?;
+    match core::eval_body(body, env) {
+        Err(e) => match e.downcast::<types::RuspThrow>() {
+            Ok(throw) if throw.tag.eqp(&tag) => Ok(throw.value),
+            Ok(throw) => Err(throw.into()),
+            Err(e) => Err(e),
+        },
+        res => res,
+    }
+});
+
+defun!(throw, arg, env, (tag, value), {
+    Err(anyhow::anyhow!(types::RuspThrow {
+        tag: core::eval(tag, env)?,
+        value: core::eval(value, env)?,
     }))
 });
 
-defun!(apply, arg, env, (func_, args_), {
-    let func = core::eval(func_, env)?;
-    let args = core::eval(args_, env)?;
+fn symbol_exp(s: &str) -> types::RuspExp {
+    types::RuspExp::Atom(types::RuspAtom::Symbol(s.to_string()))
+}
+
+fn string_exp(s: &str) -> types::RuspExp {
+    types::RuspExp::Atom(types::RuspAtom::String(s.to_string()))
+}
+
+// Raise `(symbol message)`, the shape `error` and `user-error` produce.
+fn signal_message(symbol: &str, message: String) -> anyhow::Error {
+    anyhow::anyhow!(types::RuspSignal {
+        symbol: symbol.to_string(),
+        data: std::iter::once(string_exp(&message)).collect(),
+    })
+}
+
+// The data list for a built-in error.
+fn error_data(err: &types::RuspErr) -> Vec<types::RuspExp> {
+    match err {
+        types::RuspErr::WrongTypeArgument { expected, actual } => {
+            vec![symbol_exp(expected), actual.clone()]
+        }
+        types::RuspErr::WrongNumberOfArguments {
+            required,
+            allowed,
+            actual,
+        } => vec![
+            types::RuspExp::Cons {
+                car: std::sync::Arc::new(types::RuspExp::Atom(types::RuspAtom::Int(
+                    *required as i64,
+                ))),
+                cdr: std::sync::Arc::new(allowed.map_or(symbol_exp("many"), |x| {
+                    types::RuspExp::Atom(types::RuspAtom::Int(x as i64))
+                })),
+            },
+            types::RuspExp::Atom(types::RuspAtom::Int(*actual as i64)),
+        ],
+        types::RuspErr::VoidVariable { name } | types::RuspErr::VoidFunction { name } => {
+            vec![symbol_exp(name)]
+        }
+        types::RuspErr::ExcessiveLispNesting { depth } => {
+            vec![types::RuspExp::Atom(types::RuspAtom::Int(*depth as i64))]
+        }
+        types::RuspErr::ReaderInvalidEscapeError { char } => {
+            vec![string_exp(&format!("\\{}", char))]
+        }
+        types::RuspErr::ReaderUnclosedStringError => vec![string_exp("unterminated string")],
+        _ => vec![],
+    }
+}
+
+// The `(symbol . data)` object describing an error, or None for a `throw`
+// that is on its way to a `catch` and must not be intercepted.
+fn error_object(e: &anyhow::Error) -> Option<types::RuspExp> {
+    if e.downcast_ref::<types::RuspThrow>().is_some() {
+        return None;
+    }
+    if let Some(signal) = e.downcast_ref::<types::RuspSignal>() {
+        return Some(types::RuspExp::Cons {
+            car: std::sync::Arc::new(symbol_exp(&signal.symbol)),
+            cdr: std::sync::Arc::new(signal.data.clone()),
+        });
+    }
+
+    let (symbol, data) = match e.downcast_ref::<types::RuspErr>() {
+        Some(err) => (err.symbol(), error_data(err)),
+        None => {
+            // anything else, such as an I/O error, is a plain `error`; leave
+            // out the source position, which `core::eval` only ever adds as
+            // the outermost context
+            let skip = usize::from(e.downcast_ref::<types::RuspSpan>().is_some());
+            let message = e
+                .chain()
+                .skip(skip)
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(": ");
+            ("error", vec![string_exp(&message)])
+        }
+    };
+    Some(std::iter::once(symbol_exp(symbol)).chain(data).collect())
+}
+
+// `t` handles every error, otherwise the handler names one condition or a
+// list of them.
+fn handler_matches(spec: &types::RuspExp, conditions: &[String]) -> anyhow::Result<bool> {
+    if spec.consp() {
+        for x in spec {
+            if handler_matches(x?, conditions)? {
+                return Ok(true);
+            }
+        }
+        return Ok(false);
+    }
+    let spec = spec.as_symbol()?;
+    Ok(spec == "t" || conditions.iter().any(|x| x == spec))
+}
 
-    core::eval_lambda(&func, &args, env)
+defun!(signal, arg, env, (symbol, data), {
+    let symbol = core::eval(symbol, env)?;
+    Err(anyhow::anyhow!(types::RuspSignal {
+        symbol: symbol.as_symbol()?.to_string(),
+        data: core::eval(data, env)?,
+    }))
 });
+
+// Emacs-style `format`: `%s` prints like `princ` and `%S` like `prin1`, and
+// `%d`, `%o`, `%x`, `%X`, `%c`, `%f` and `%%` work as usual. A precision such
+// as `%.2f` is accepted, but field widths and flags are not.
+fn format_impl(fmt: &str, args: &[types::RuspExp]) -> anyhow::Result<String> {
+    let mut res = String::new();
+    let mut args = args.iter();
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            res.push(c);
+            continue;
+        }
+
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = String::new();
+            while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(*d);
+                chars.next();
+            }
+            precision = Some(digits.parse::<usize>().unwrap_or(0));
+        }
+
+        let spec = chars.next().ok_or_else(|| {
+            signal_message(
+                "error",
+                "Format string ends in middle of format specifier".into(),
+            )
+        })?;
+        if spec == '%' {
+            res.push('%');
+            continue;
+        }
+        let arg = args.next().ok_or_else(|| {
+            signal_message("error", "Not enough arguments for format string".into())
+        })?;
+
+        let text = match (spec, arg) {
+            ('s', types::RuspExp::Atom(types::RuspAtom::String(s))) => s.clone(),
+            ('s', x) | ('S', x) => x.to_string(),
+            ('d', types::RuspExp::Atom(types::RuspAtom::Float(f))) => {
+                (f.trunc() as i64).to_string()
+            }
+            ('d', types::RuspExp::Atom(types::RuspAtom::Ratio(r))) => r.trunc().to_string(),
+            ('d', x) if x.intp() => x.as_bigint().unwrap().to_string(),
+            ('o', x) if x.intp() => format!("{:o}", x.as_bigint().unwrap()),
+            ('x', x) if x.intp() => format!("{:x}", x.as_bigint().unwrap()),
+            ('X', x) if x.intp() => format!("{:X}", x.as_bigint().unwrap()),
+            ('c', types::RuspExp::Atom(types::RuspAtom::Int(i))) => {
+                match u32::try_from(*i).ok().and_then(char::from_u32) {
+                    Some(c) => c.to_string(),
+                    None => {
+                        return Err(signal_message("error", format!("Invalid character: {}", i)))
+                    }
+                }
+            }
+            ('f', x) if x.numberp() => {
+                format!("{:.*}", precision.unwrap_or(6), x.as_float().unwrap())
+            }
+            ('d' | 'o' | 'x' | 'X' | 'c' | 'f', _) => {
+                return Err(signal_message(
+                    "error",
+                    "Format specifier doesn't match argument type".into(),
+                ))
+            }
+            _ => {
+                return Err(signal_message(
+                    "error",
+                    format!("Invalid format operation %{}", spec),
+                ))
+            }
+        };
+
+        match (spec, precision) {
+            ('s' | 'S', Some(n)) => res.extend(text.chars().take(n)),
+            _ => res.push_str(&text),
+        }
+    }
+    Ok(res)
+}
+
+// Evaluate a format string and its arguments, as `format` and the error
+// functions take them.
+fn format_args(
+    fmt: &types::RuspExp,
+    args: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<String> {
+    let fmt = core::eval(fmt, env)?;
+    let args = args
+        .into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<Result<Vec<_>, _>>()?;
+    match &fmt {
+        types::RuspExp::Atom(types::RuspAtom::String(s)) => format_impl(s, &args),
+        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "stringp".into(),
+            actual: fmt.clone()
+        })),
+    }
+}
+
+defun!(format, arg, env, (fmt, &rest args), {
+    Ok(string_exp(&format_args(fmt, args, env)?))
+});
+
+defun!(error, arg, env, (fmt, &rest args), {
+    Err(signal_message("error", format_args(fmt, args, env)?))
+});
+
+defun!(user_error, arg, env, (fmt, &rest args), {
+    Err(signal_message("user-error", format_args(fmt, args, env)?))
+});
+
+// Write `text` out right away, so that it is not lost if a script goes on to
+// fail.
+fn write_output(mut out: impl std::io::Write, text: &str) -> anyhow::Result<()> {
+    out.write_all(text.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
+
+// Print an object as `%s` formats it, without quoting strings.
+defun!(princ, arg, env, (object), {
+    let object = core::eval(object, env)?;
+    let text = match &object {
+        types::RuspExp::Atom(types::RuspAtom::String(s)) => s.clone(),
+        x => x.to_string(),
+    };
+    write_output(std::io::stdout(), &text)?;
+    Ok(object)
+});
+
+// Print an object readably, as `%S` formats it.
+defun!(prin1, arg, env, (object), {
+    let object = core::eval(object, env)?;
+    write_output(std::io::stdout(), &object.to_string())?;
+    Ok(object)
+});
+
+// Like `prin1`, but on a line of its own.
+defun!(print, arg, env, (object), {
+    let object = core::eval(object, env)?;
+    write_output(std::io::stdout(), &format!("\n{}\n", object))?;
+    Ok(object)
+});
+
+// Print a formatted message and a newline to standard error, as Emacs does
+// when running a script.
+defun!(message, arg, env, (fmt, &rest args), {
+    let text = format_args(fmt, args, env)?;
+    write_output(std::io::stderr(), &format!("{}\n", text))?;
+    Ok(string_exp(&text))
+});
+
+defspecial!(condition_case, arg, env, (var, bodyform, &rest handlers), {
+    let var = var.as_symbol()?;
+    let bind = |value: types::RuspExp| {
+        let new_env = types::RuspEnv::with_outer(env);
+        if var != "nil" {
+            new_env.define_variable(var, value);
+        }
+        new_env
+    };
+
+    match core::eval(bodyform, env) {
+        Ok(value) => {
+            for handler in handlers {
+                let handler = handler?;
+                if let types::RuspExp::Atom(types::RuspAtom::Keyword(k)) = handler.car()? {
+                    if k == "success" {
+                        return core::eval_body_tail(handler.cdr()?, &bind(value));
+                    }
+                }
+            }
+            Ok(types::RuspTail::Value(value))
+        }
+        Err(e) => {
+            let object = match error_object(&e) {
+                Some(object) => object,
+                None => return Err(e),
+            };
+            let conditions = types::error_conditions(object.car()?.as_symbol()?);
+            for handler in handlers {
+                let handler = handler?;
+                if let types::RuspExp::Atom(types::RuspAtom::Keyword(_)) = handler.car()? {
+                    continue;
+                }
+                if handler_matches(handler.car()?, &conditions)? {
+                    return core::eval_body_tail(handler.cdr()?, &bind(object));
+                }
+            }
+            Err(e)
+        }
+    }
+});
+
+defun!(ignore_errors, arg, env, _, {
+    match core::eval_body(arg, env) {
+        Err(e) if error_object(&e).is_some() => Ok(types::nil!()),
+        res => res,
+    }
+});
+
+defun!(unwind_protect, arg, env, (bodyform, &rest unwindforms), {
+    let res = core::eval(bodyform, env);
+    core::eval_body(unwindforms, env)?;
+    res
+});
+
+defun!(error_message_string, arg, env, (err_), {
+    let err = core::eval(err_, env)?;
+    let symbol = err.car()?.as_symbol()?;
+    if symbol == "error" || symbol == "user-error" {
+        if let types::RuspExp::Atom(types::RuspAtom::String(message)) = err.cdr()?.car()? {
+            return Ok(string_exp(message));
+        }
+    }
+    Ok(string_exp(&err.to_string()))
+});
+
+defspecial!(progn, arg, env, _, { core::eval_body_tail(arg, env) });
+
+defun!(set, arg, env, (sym_, val), {
+    let sym = core::eval(sym_, env)?;
+    match &sym {
+        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => {
+            let val = core::eval(val, env)?;
+            env.set_variable(s, val.clone());
+            Ok(val)
+        }
+        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "symbolp".into(),
+            actual: sym.clone()
+        })),
+    }
+});
+
+defun!(setq, arg, env, (sym, val), {
+    match &**sym {
+        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => {
+            let val = core::eval(val, env)?;
+            env.set_variable(s, val.clone());
+            Ok(val)
+        }
+        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "symbolp".into(),
+            actual: (**sym).clone()
+        })),
+    }
+});
+
+defun!(defun_, arg, env, (name, params, &rest body), {
+    let sym = name.as_symbol()?;
+    env.global().define_function(
+        sym,
+        types::RuspExp::Atom(types::RuspAtom::Lambda {
+            params: params.clone(),
+            body: std::sync::Arc::new(body.clone()),
+            env: env.clone(),
+        }),
+    );
+    Ok((**name).clone())
+});
+
+defun!(defmacro, arg, env, (name, params, &rest body), {
+    let sym = name.as_symbol()?;
+    let expander = types::RuspExp::Atom(types::RuspAtom::Lambda {
+        params: params.clone(),
+        body: std::sync::Arc::new(body.clone()),
+        env: env.clone(),
+    });
+    env.global().define_function(
+        sym,
+        types::RuspExp::Atom(types::RuspAtom::Macro(std::sync::Arc::new(expander))),
+    );
+    Ok((**name).clone())
+});
+
+defun!(macroexpand_1, arg, env, (form_), {
+    let form = core::eval(form_, env)?;
+    Ok(core::macroexpand_1(&form, env)?.unwrap_or(form))
+});
+
+defun!(macroexpand, arg, env, (form), {
+    core::macroexpand(&core::eval(form, env)?, env)
+});
+
+defun!(macroexpand_all, arg, env, (form), {
+    core::macroexpand_all(&core::eval(form, env)?, env)
+});
+
+fn check_docstring(doc: &types::RuspExp) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        doc.nilp() || doc.stringp(),
+        types::RuspErr::WrongTypeArgument {
+            expected: "stringp".into(),
+            actual: doc.clone()
+        }
+    );
+    Ok(())
+}
+
+defun!(defvar, arg, env, (name, &optional val, &optional doc), {
+    let sym = name.as_symbol()?;
+    check_docstring(doc)?;
+    let global = env.global();
+    if arg.cdr()?.consp() && global.get_variable(sym).is_err() {
+        let val = core::eval(val, env)?;
+        global.define_variable(sym, val);
+    }
+    Ok((**name).clone())
+});
+
+defun!(defconst, arg, env, (name, val, &optional doc), {
+    let sym = name.as_symbol()?;
+    check_docstring(doc)?;
+    let val = core::eval(val, env)?;
+    env.global().define_variable(sym, val);
+    Ok((**name).clone())
+});
+
+defun!(fset, arg, env, (sym_, def_), {
+    let sym = core::eval(sym_, env)?;
+    let def = core::eval(def_, env)?;
+    env.global().define_function(sym.as_symbol()?, def.clone());
+    Ok(def)
+});
+
+defun!(symbol_function, arg, env, (sym_), {
+    let sym = core::eval(sym_, env)?;
+    Ok(env
+        .get_function(sym.as_symbol()?)
+        .unwrap_or_else(|_| types::nil!()))
+});
+
+// A binding is either `sym`, `(sym)` or `(sym val)`.
+fn parse_binding(binding: &types::RuspExp) -> anyhow::Result<(&str, Option<&types::RuspExp>)> {
+    if binding.symbolp() {
+        return Ok((binding.as_symbol()?, None));
+    }
+
+    let malformed = || signal_message("error", format!("Malformed let binding: {}", binding));
+    let sym = binding.car().map_err(|_| malformed())?.as_symbol()?;
+    let rest = binding.cdr()?;
+    if rest.nilp() {
+        return Ok((sym, None));
+    }
+    if !rest.consp() || rest.cdr()?.non_nil_p() {
+        return Err(malformed());
+    }
+    Ok((sym, Some(rest.car()?)))
+}
+
+fn eval_binding_value(
+    val: Option<&types::RuspExp>,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    match val {
+        Some(val) => core::eval(val, env),
+        None => Ok(types::nil!()),
+    }
+}
+
+defspecial!(let_, arg, env, (bindings, &rest body), {
+    let new_env = types::RuspEnv::with_outer(env);
+    for binding in &**bindings {
+        let (sym, val) = parse_binding(binding?)?;
+        new_env.define_variable(sym, eval_binding_value(val, env)?);
+    }
+    core::eval_body_tail(body, &new_env)
+});
+
+defspecial!(let_star, arg, env, (bindings, &rest body), {
+    let mut new_env = types::RuspEnv::with_outer(env);
+    for binding in &**bindings {
+        let (sym, val) = parse_binding(binding?)?;
+        let val = eval_binding_value(val, &new_env)?;
+        new_env = types::RuspEnv::with_outer(&new_env);
+        new_env.define_variable(sym, val);
+    }
+    core::eval_body_tail(body, &new_env)
+});
+
+fn bool_exp(b: bool) -> types::RuspExp {
+    if b {
+        return types::t!();
+    }
+    types::nil!()
+}
+
+defun!(eq, arg, env, (a, b), {
+    Ok(bool_exp(core::eval(a, env)?.eqp(&core::eval(b, env)?)))
+});
+
+defun!(equal, arg, env, (a, b), {
+    Ok(bool_exp(core::eval(a, env)? == core::eval(b, env)?))
+});
+
+defun!(atom, arg, env, (x), {
+    Ok(bool_exp(core::eval(x, env)?.atom()))
+});
+
+defun!(consp, arg, env, (x), {
+    Ok(bool_exp(core::eval(x, env)?.consp()))
+});
+
+defun!(listp, arg, env, (x), {
+    Ok(bool_exp(core::eval(x, env)?.listp()))
+});
+
+defun!(cons, arg, env, (car, cdr), {
+    Ok(types::RuspExp::Cons {
+        car: std::sync::Arc::new(core::eval(car, env)?),
+        cdr: std::sync::Arc::new(core::eval(cdr, env)?),
+    })
+});
+
+defun!(car, arg, env, (x), {
+    let lst = core::eval(x, env)?;
+    match lst.as_list()? {
+        types::RuspExp::Cons { car, .. } => Ok((**car).clone()),
+        _ => Ok(types::nil!()),
+    }
+});
+
+defun!(cdr, arg, env, (x), {
+    let lst = core::eval(x, env)?;
+    match lst.as_list()? {
+        types::RuspExp::Cons { cdr, .. } => Ok((**cdr).clone()),
+        _ => Ok(types::nil!()),
+    }
+});
+
+defun!(list, arg, env, _, {
+    arg.into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<anyhow::Result<types::RuspExp>>()
+});
+
+defun!(append, arg, env, _, {
+    let mut lsts = arg
+        .into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut res = lsts.pop().unwrap_or_else(|| types::nil!());
+
+    for lst in lsts.iter().rev() {
+        let elms = lst
+            .as_list()?
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for x in elms.into_iter().rev() {
+            res = types::RuspExp::Cons {
+                car: x.clone(),
+                cdr: std::sync::Arc::new(res),
+            };
+        }
+    }
+    Ok(res)
+});
+
+fn nthcdr_impl(n: i64, lst: &types::RuspExp) -> anyhow::Result<&types::RuspExp> {
+    let mut cell = lst.as_list()?;
+    for _ in 0..n {
+        if cell.nilp() {
+            break;
+        }
+        cell = cell.cdr()?.as_list()?;
+    }
+    Ok(cell)
+}
+
+defun!(nthcdr, arg, env, (n, lst), {
+    let n = core::eval(n, env)?.as_int()?;
+    let lst = core::eval(lst, env)?;
+    Ok(nthcdr_impl(n, &lst)?.clone())
+});
+
+defun!(nth, arg, env, (n, lst), {
+    let n = core::eval(n, env)?.as_int()?;
+    let lst = core::eval(lst, env)?;
+    match nthcdr_impl(n, &lst)? {
+        types::RuspExp::Cons { car, .. } => Ok((**car).clone()),
+        _ => Ok(types::nil!()),
+    }
+});
+
+defun!(length, arg, env, (x), {
+    let seq = core::eval(x, env)?;
+    let len = match &seq {
+        types::RuspExp::Atom(types::RuspAtom::String(s)) => s.chars().count(),
+        _ => seq
+            .as_list()?
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .len(),
+    };
+    Ok(types::RuspExp::Atom(types::RuspAtom::Int(len as i64)))
+});
+
+defun!(reverse, arg, env, (x), {
+    let lst = core::eval(x, env)?;
+    let mut res = types::nil!();
+    for x in lst.as_list()? {
+        res = types::RuspExp::Cons {
+            car: x?.clone(),
+            cdr: std::sync::Arc::new(res),
+        };
+    }
+    Ok(res)
+});
+
+defun!(last, arg, env, (x), {
+    let lst = core::eval(x, env)?;
+    let mut cell = lst.as_list()?;
+    while let types::RuspExp::Cons { cdr, .. } = cell {
+        if !cdr.consp() {
+            break;
+        }
+        cell = cdr;
+    }
+    Ok(cell.clone())
+});
+
+defun!(member, arg, env, (elt, lst), {
+    let elt = core::eval(elt, env)?;
+    let lst = core::eval(lst, env)?;
+    let mut cell = lst.as_list()?;
+    while let types::RuspExp::Cons { car, cdr } = cell {
+        if **car == elt {
+            return Ok(cell.clone());
+        }
+        cell = cdr.as_list()?;
+    }
+    Ok(types::nil!())
+});
+
+fn assoc_impl(
+    key: &types::RuspExp,
+    alist: &types::RuspExp,
+    pred: fn(&types::RuspExp, &types::RuspExp) -> bool,
+) -> anyhow::Result<types::RuspExp> {
+    for x in alist.as_list()? {
+        let elm = x?;
+        if let types::RuspExp::Cons { car, .. } = &**elm {
+            if pred(key, car) {
+                return Ok((**elm).clone());
+            }
+        }
+    }
+    Ok(types::nil!())
+}
+
+defun!(assoc, arg, env, (key, alist), {
+    let key = core::eval(key, env)?;
+    let alist = core::eval(alist, env)?;
+    assoc_impl(&key, &alist, |a, b| a == b)
+});
+
+defun!(assq, arg, env, (key, alist), {
+    let key = core::eval(key, env)?;
+    let alist = core::eval(alist, env)?;
+    assoc_impl(&key, &alist, types::RuspExp::eqp)
+});
+
+defun!(quote, arg, _env, (exp), { Ok((**exp).clone()) });
+
+// The argument of `(symbol arg)`, as in the forms `'`, `` ` ``, `,` and `,@`
+// read as.
+pub fn form_arg<'a>(x: &'a types::RuspExp, symbol: &str) -> Option<&'a types::RuspExp> {
+    if let types::RuspExp::Cons { car, cdr } = x {
+        if let (
+            types::RuspExp::Atom(types::RuspAtom::Symbol(s)),
+            types::RuspExp::Cons { car: arg, cdr: end },
+        ) = (&**car, &**cdr)
+        {
+            if s == symbol && end.nilp() {
+                return Some(arg);
+            }
+        }
+    }
+    None
+}
+
+pub fn wrap_form(symbol: &str, arg: types::RuspExp) -> types::RuspExp {
+    [symbol_exp(symbol), arg].into_iter().collect()
+}
+
+// Fill in a backquote template. `depth` counts the enclosing backquotes, and
+// only unquotes that bring it down to zero are evaluated; deeper ones are
+// kept with their own contents filled in one level further.
+fn backquote_impl(
+    x: &types::RuspExp,
+    depth: usize,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    if let Some(arg) = form_arg(x, "unquote") {
+        if depth == 1 {
+            return core::eval(arg, env);
+        }
+        return Ok(wrap_form("unquote", backquote_impl(arg, depth - 1, env)?));
+    }
+    if let Some(arg) = form_arg(x, "backquote") {
+        return Ok(wrap_form("backquote", backquote_impl(arg, depth + 1, env)?));
+    }
+
+    let mut items = vec![];
+    let mut cell = x;
+    let tail = loop {
+        match cell {
+            types::RuspExp::Cons { car, cdr } => {
+                items.extend(backquote_element(car, depth, env)?);
+                cell = cdr;
+                // `(a . ,b)` reads as `(a unquote b)`
+                if form_arg(cell, "unquote").is_some() || form_arg(cell, "backquote").is_some() {
+                    break backquote_impl(cell, depth, env)?;
+                }
+            }
+            _ => break cell.clone(),
+        }
+    };
+    Ok(items
+        .into_iter()
+        .rev()
+        .fold(tail, |cdr, car| types::RuspExp::Cons {
+            car: std::sync::Arc::new(car),
+            cdr: std::sync::Arc::new(cdr),
+        }))
+}
+
+// The items one list element of a template contributes. Splicing can yield
+// any number of them, also when it happens inside a deeper unquote such as
+// `,@,@x`.
+fn backquote_element(
+    x: &types::RuspExp,
+    depth: usize,
+    env: &types::RuspEnv,
+) -> anyhow::Result<Vec<types::RuspExp>> {
+    if let Some(arg) = form_arg(x, "unquote-splicing") {
+        if depth == 1 {
+            return core::eval(arg, env)?.to_vec();
+        }
+    }
+    for symbol in ["unquote", "unquote-splicing"] {
+        if let Some(arg) = form_arg(x, symbol) {
+            if depth == 2 {
+                if let Some(inner) = form_arg(arg, "unquote-splicing") {
+                    let values = core::eval(inner, env)?.to_vec()?;
+                    return Ok(values.into_iter().map(|v| wrap_form(symbol, v)).collect());
+                }
+            }
+            if depth > 1 {
+                return Ok(vec![wrap_form(
+                    symbol,
+                    backquote_impl(arg, depth - 1, env)?,
+                )]);
+            }
+        }
+    }
+    Ok(vec![backquote_impl(x, depth, env)?])
+}
+
+defun!(backquote, arg, env, (exp), { backquote_impl(exp, 1, env) });
+
+defun!(lambda, arg, env, (params, &rest body), {
+    Ok(types::RuspExp::Atom(types::RuspAtom::Lambda {
+        params: params.clone(),
+        body: std::sync::Arc::new(body.clone()),
+        env: env.clone(),
+    }))
+});
+
+defun!(load, arg, env, (path_), {
+    let path = core::eval(path_, env)?;
+    match &path {
+        types::RuspExp::Atom(types::RuspAtom::String(path)) => {
+            core::load(path, env)?;
+            Ok(types::t!())
+        }
+        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "stringp".into(),
+            actual: path.clone()
+        })),
+    }
+});
+
+defun!(apply, arg, env, (func_, &rest args_), {
+    let func = core::eval(func_, env)?;
+    let mut args = args_
+        .into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let Some(last) = args.pop() {
+        args.extend(last.to_vec()?);
+    }
+
+    core::funcall(&func, args, env)
+});
+
+defun!(funcall, arg, env, (func_, &rest args_), {
+    let func = core::eval(func_, env)?;
+    let args = args_
+        .into_iter()
+        .map(|x| core::eval(x?, env))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    core::funcall(&func, args, env)
+});
+
+fn map_impl(
+    func: &types::RuspExp,
+    seq: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<Vec<types::RuspExp>> {
+    seq.to_vec()?
+        .into_iter()
+        .map(|x| core::funcall(func, vec![x], env))
+        .collect()
+}
+
+defun!(mapcar, arg, env, (func, seq), {
+    let func = core::eval(func, env)?;
+    let seq = core::eval(seq, env)?;
+    Ok(map_impl(&func, &seq, env)?.into_iter().collect())
+});
+
+defun!(mapc, arg, env, (func, seq), {
+    let func = core::eval(func, env)?;
+    let seq = core::eval(seq, env)?;
+    map_impl(&func, &seq, env)?;
+    Ok(seq)
+});
+
+defun!(mapcan, arg, env, (func, seq), {
+    let func = core::eval(func, env)?;
+    let seq = core::eval(seq, env)?;
+    let mut res = vec![];
+    for x in map_impl(&func, &seq, env)? {
+        res.extend(x.to_vec()?);
+    }
+    Ok(res.into_iter().collect())
+});
+
+fn filter_impl(
+    pred: &types::RuspExp,
+    seq: &types::RuspExp,
+    keep: bool,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    let mut res = vec![];
+    for x in seq.to_vec()? {
+        if core::funcall(pred, vec![x.clone()], env)?.non_nil_p() == keep {
+            res.push(x);
+        }
+    }
+    Ok(res.into_iter().collect())
+}
+
+defun!(seq_filter, arg, env, (pred, seq), {
+    let pred = core::eval(pred, env)?;
+    let seq = core::eval(seq, env)?;
+    filter_impl(&pred, &seq, true, env)
+});
+
+defun!(remove_if, arg, env, (pred, seq), {
+    let pred = core::eval(pred, env)?;
+    let seq = core::eval(seq, env)?;
+    filter_impl(&pred, &seq, false, env)
+});
+
+defun!(reduce, arg, env, (func, seq, &optional init), {
+    let func = core::eval(func, env)?;
+    let mut iter = core::eval(seq, env)?.to_vec()?.into_iter();
+    let mut acc = if arg.cdr()?.cdr()?.consp() {
+        core::eval(init, env)?
+    } else {
+        match iter.next() {
+            Some(x) => x,
+            None => return core::funcall(&func, vec![], env),
+        }
+    };
+    for x in iter {
+        acc = core::funcall(&func, vec![acc, x], env)?;
+    }
+    Ok(acc)
+});
+
+// Stable merge sort; the predicate may fail, so `slice::sort_by` can't be used.
+fn merge_sort(
+    mut lst: Vec<types::RuspExp>,
+    pred: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<Vec<types::RuspExp>> {
+    if lst.len() <= 1 {
+        return Ok(lst);
+    }
+    let right = merge_sort(lst.split_off(lst.len() / 2), pred, env)?;
+    let left = merge_sort(lst, pred, env)?;
+
+    let mut res = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        if core::funcall(pred, vec![r.clone(), l.clone()], env)?.non_nil_p() {
+            res.push(right.next().unwrap());
+        } else {
+            res.push(left.next().unwrap());
+        }
+    }
+    res.extend(left);
+    res.extend(right);
+    Ok(res)
+}
+
+defun!(sort, arg, env, (seq, pred), {
+    let seq = core::eval(seq, env)?;
+    let pred = core::eval(pred, env)?;
+    Ok(merge_sort(seq.to_vec()?, &pred, env)?.into_iter().collect())
+});
+
+#[cfg(test)]
+mod tests {
+    use crate::core;
+    use crate::types;
+
+    #[test]
+    fn test_progn() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(progn)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(progn 1 2 3)", &env).unwrap(), "3");
+        assert_eq!(core::rep("(progn (setq a 1) (+ a 1))", &env).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_lambda_body() {
+        let env = core::default_env();
+        assert_eq!(core::rep("((lambda (x)) 1)", &env).unwrap(), "nil");
+        assert_eq!(
+            core::rep("((lambda (x) (setq y (* x 2)) (+ y 1)) 20)", &env).unwrap(),
+            "41"
+        );
+        assert_eq!(
+            core::rep("(lambda (x) (setq y x) y)", &env).unwrap(),
+            "#<lambda (x) (setq y x) y>"
+        );
+    }
+
+    #[test]
+    fn test_defun() {
+        let env = core::default_env();
+        assert_eq!(
+            core::rep("(defun square (x) \"Square X.\" (* x x))", &env).unwrap(),
+            "square"
+        );
+        assert_eq!(core::rep("(square 7)", &env).unwrap(), "49");
+
+        core::rep(
+            "(defun fact (n) (if (<= n 1) 1 (* n (fact (- n 1)))))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(core::rep("(fact 10)", &env).unwrap(), "3628800");
+
+        core::rep("((lambda () (defun inner () 42)))", &env).unwrap();
+        assert_eq!(core::rep("(inner)", &env).unwrap(), "42");
+    }
+
+    #[test]
+    fn test_defvar_defconst() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(defvar x 1 \"An x.\")", &env).unwrap(), "x");
+        assert_eq!(core::rep("(defvar x 2)", &env).unwrap(), "x");
+        assert_eq!(core::rep("x", &env).unwrap(), "1");
+        assert_eq!(core::rep("(defvar undefined)", &env).unwrap(), "undefined");
+        assert!(core::rep("undefined", &env).is_err());
+
+        assert_eq!(core::rep("(defconst c 1 \"A c.\")", &env).unwrap(), "c");
+        assert_eq!(core::rep("(defconst c 2)", &env).unwrap(), "c");
+        assert_eq!(core::rep("c", &env).unwrap(), "2");
+
+        assert!(core::rep("(defvar y 1 2)", &env).is_err());
+        assert!(core::rep("(defconst 1 2)", &env).is_err());
+    }
+
+    #[test]
+    fn test_fset_symbol_function() {
+        let env = core::default_env();
+        core::rep("(fset 'inc (lambda (x) (+ x 1)))", &env).unwrap();
+        assert_eq!(core::rep("(inc 1)", &env).unwrap(), "2");
+        assert_eq!(
+            core::rep("(symbol-function 'inc)", &env).unwrap(),
+            "#<lambda (x) (+ x 1)>"
+        );
+        assert_eq!(
+            core::rep("(symbol-function 'no-such)", &env).unwrap(),
+            "nil"
+        );
+
+        core::rep("(fset 'plus (symbol-function '+))", &env).unwrap();
+        assert_eq!(core::rep("(plus 1 2)", &env).unwrap(), "3");
+    }
+
+    #[test]
+    fn test_let() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(let ((x 1) (y 2)) (+ x y))", &env).unwrap(), "3");
+        assert_eq!(core::rep("(let (x (y)) y)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(let (x) x)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(let ())", &env).unwrap(), "nil");
+
+        core::rep("(setq x 10)", &env).unwrap();
+        assert_eq!(core::rep("(let ((x 1) (y x)) y)", &env).unwrap(), "10");
+        assert_eq!(core::rep("(let ((x 1)) (setq x 2) x)", &env).unwrap(), "2");
+        assert_eq!(core::rep("x", &env).unwrap(), "10");
+    }
+
+    #[test]
+    fn test_let_star() {
+        let env = core::default_env();
+        core::rep("(setq x 10)", &env).unwrap();
+        assert_eq!(core::rep("(let* ((x 1) (y x)) y)", &env).unwrap(), "1");
+        assert_eq!(
+            core::rep("(let* ((x 1) (f (lambda () x)) (x 2)) (apply f nil))", &env).unwrap(),
+            "1"
+        );
+        assert_eq!(core::rep("x", &env).unwrap(), "10");
+    }
+
+    #[test]
+    fn test_let_malformed() {
+        let env = core::default_env();
+        let err = core::rep("(let ((1 2)) nil)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongTypeArgument {
+                expected: "symbolp".into(),
+                actual: types::RuspExp::Atom(types::RuspAtom::Int(1)),
+            }
+        );
+
+        for (input, expected) in [
+            (
+                "(condition-case err (let ((x 1 2)) nil) (error err))",
+                "(error \"Malformed let binding: (x 1 2)\")",
+            ),
+            (
+                "(condition-case err (let* ((x . 1)) nil) (error err))",
+                "(error \"Malformed let binding: (x . 1)\")",
+            ),
+            (
+                "(condition-case err (let (1) nil) (error err))",
+                "(error \"Malformed let binding: 1\")",
+            ),
+        ] {
+            assert_eq!(core::rep(input, &env).unwrap(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_if() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(if t 1 2)", &env).unwrap(), "1");
+        assert_eq!(core::rep("(if nil 1 2)", &env).unwrap(), "2");
+        assert_eq!(core::rep("(if nil 1)", &env).unwrap(), "nil");
+        assert_eq!(
+            core::rep("(if nil 1 (setq a 2) (+ a 1))", &env).unwrap(),
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(cond)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(cond (nil 1) (t 2))", &env).unwrap(), "2");
+        assert_eq!(core::rep("(cond (nil 1) (42))", &env).unwrap(), "42");
+        assert_eq!(
+            core::rep("(cond (nil 1) ((< 1 2) 2 3))", &env).unwrap(),
+            "3"
+        );
+        assert_eq!(core::rep("(cond (nil 1))", &env).unwrap(), "nil");
+        assert!(core::rep("(cond 1)", &env).is_err());
+    }
+
+    #[test]
+    fn test_when_unless() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(when t 1 2)", &env).unwrap(), "2");
+        assert_eq!(core::rep("(when nil 1 2)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(unless nil 1 2)", &env).unwrap(), "2");
+        assert_eq!(core::rep("(unless t 1 2)", &env).unwrap(), "nil");
+    }
+
+    #[test]
+    fn test_and_or_not() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(and)", &env).unwrap(), "t");
+        assert_eq!(core::rep("(and 1 2 3)", &env).unwrap(), "3");
+        assert_eq!(core::rep("(and 1 nil undefined)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(or)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(or nil 2 undefined)", &env).unwrap(), "2");
+        assert_eq!(core::rep("(or nil nil)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(not nil)", &env).unwrap(), "t");
+        assert_eq!(core::rep("(not 1)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(null nil)", &env).unwrap(), "t");
+    }
+
+    #[test]
+    fn test_while() {
+        let env = core::default_env();
+        core::rep("(setq i 0)", &env).unwrap();
+        core::rep("(setq sum 0)", &env).unwrap();
+        assert_eq!(
+            core::rep(
+                "(while (< i 100000) (setq sum (+ sum i)) (setq i (+ i 1)))",
+                &env
+            )
+            .unwrap(),
+            "nil"
+        );
+        assert_eq!(core::rep("sum", &env).unwrap(), "4999950000");
+    }
+
+    #[test]
+    fn test_dotimes() {
+        let env = core::default_env();
+        core::rep("(setq sum 0)", &env).unwrap();
+        assert_eq!(
+            core::rep("(dotimes (i 5) (setq sum (+ sum i)))", &env).unwrap(),
+            "nil"
+        );
+        assert_eq!(core::rep("sum", &env).unwrap(), "10");
+        assert_eq!(core::rep("(dotimes (i 3 i))", &env).unwrap(), "3");
+        assert!(core::rep("i", &env).is_err());
+        assert!(core::rep("(dotimes (i 1.5))", &env).is_err());
+    }
+
+    #[test]
+    fn test_dolist() {
+        let env = core::default_env();
+        core::rep("(setq sum 0)", &env).unwrap();
+        assert_eq!(
+            core::rep("(dolist (x '(1 2 3) sum) (setq sum (+ sum x)))", &env).unwrap(),
+            "6"
+        );
+        assert_eq!(core::rep("(dolist (x '(1 2 3) x))", &env).unwrap(), "nil");
+        assert!(core::rep("(dolist (x '(1 . 2)))", &env).is_err());
+    }
+
+    #[test]
+    fn test_drop_long_list() {
+        // dropping must not recurse once per cell, even on a test thread's
+        // small stack
+        let env = core::default_env();
+        assert_eq!(
+            core::rep(
+                "(progn (let ((l nil)) (dotimes (i 200000) (setq l (cons i l)))) 'ok)",
+                &env
+            )
+            .unwrap(),
+            "ok"
+        );
+
+        // cells still shared with another list survive
+        core::rep("(setq l (list 1 2 3))", &env).unwrap();
+        core::rep("(setq m (cons 0 (cdr l)))", &env).unwrap();
+        core::rep("(setq l nil)", &env).unwrap();
+        assert_eq!(core::rep("m", &env).unwrap(), "(0 2 3)");
+    }
+
+    #[test]
+    fn test_catch_throw() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(catch 'done 1 2)", &env).unwrap(), "2");
+        assert_eq!(
+            core::rep(
+                "(catch 'done (dotimes (i 10) (when (= i 3) (throw 'done i))))",
+                &env
+            )
+            .unwrap(),
+            "3"
+        );
+        assert_eq!(
+            core::rep("(catch 'outer (catch 'inner (throw 'outer 1)) 2)", &env).unwrap(),
+            "1"
+        );
+
+        let err = core::rep("(throw 'nobody 1)", &env).unwrap_err();
+        let throw = err.downcast_ref::<types::RuspThrow>().unwrap();
+        assert_eq!(throw.tag.to_string(), "nobody");
+        assert_eq!(throw.value.to_string(), "1");
+
+        // tags are compared with `eq`, so equal strings do not match
+        assert_eq!(core::rep("(catch 1 (throw 1 'ok))", &env).unwrap(), "ok");
+        let err = core::rep("(catch \"a\" (throw \"a\" 1))", &env).unwrap_err();
+        assert!(err.downcast_ref::<types::RuspThrow>().is_some());
+        let err = core::rep("(catch '(a) (throw '(a) 1))", &env).unwrap_err();
+        assert!(err.downcast_ref::<types::RuspThrow>().is_some());
+    }
+
+    #[test]
+    fn test_condition_case() {
+        let env = core::default_env();
+        // handlers get the offending object itself, not a printed copy
+        for (input, expected) in [
+            (
+                "(condition-case err (car (lambda (x) (* x 2))) (error (funcall (nth 2 err) 5)))",
+                "10",
+            ),
+            (
+                "(condition-case err (car (symbol-function '+)) (error (funcall (nth 2 err) 1 2)))",
+                "3",
+            ),
+            (
+                "(condition-case err (car \"a b\") (error (nth 2 err)))",
+                "\"a b\"",
+            ),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        // other errors carry their message, without the source position
+        let message = core::rep(
+            "(condition-case err (load \"/nonexistent/file.lisp\") (error (nth 1 err)))",
+            &env,
+        )
+        .unwrap();
+        assert!(
+            message.starts_with("\"Failed to read file: /nonexistent/file.lisp: "),
+            "{}",
+            message
+        );
+        assert_eq!(
+            core::rep("(condition-case err (car 1) (error err))", &env).unwrap(),
+            "(wrong-type-argument listp 1)"
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (undefined) (void-variable 'var) ((void-function arith-error) (cdr err)))",
+                &env
+            )
+            .unwrap(),
+            "(undefined)"
+        );
+        assert_eq!(
+            core::rep("(condition-case nil (cons 1) (t 'caught))", &env).unwrap(),
+            "caught"
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (+ 1 2) (error 0) (:success (* err 10)))",
+                &env
+            )
+            .unwrap(),
+            "30"
+        );
+        assert_eq!(
+            core::rep("(condition-case err (+ 1 2) (error 0))", &env).unwrap(),
+            "3"
+        );
+
+        // the parent hierarchy decides which handler runs
+        core::rep("(setq max-lisp-eval-depth 100)", &env).unwrap();
+        core::rep("(defun runaway (n) (+ 1 (runaway n)))", &env).unwrap();
+        assert_eq!(
+            core::rep(
+                "(condition-case err (runaway 0) (recursion-error (car err)))",
+                &env
+            )
+            .unwrap(),
+            "excessive-lisp-nesting"
+        );
+        core::rep("(setq max-lisp-eval-depth 1600)", &env).unwrap();
+
+        // unhandled conditions and throws pass through
+        let err = core::rep("(condition-case nil (car 1) (void-variable nil))", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongTypeArgument {
+                expected: "listp".into(),
+                actual: types::RuspExp::Atom(types::RuspAtom::Int(1))
+            }
+        );
+        assert_eq!(
+            core::rep(
+                "(catch 'done (condition-case nil (throw 'done 1) (t 2)))",
+                &env
+            )
+            .unwrap(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_signal_error() {
+        let env = core::default_env();
+        assert_eq!(
+            core::rep(
+                "(condition-case err (signal 'my-error '(1 2)) (error (cdr err)))",
+                &env
+            )
+            .unwrap(),
+            "(1 2)"
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (signal 'wrong-type-argument '(listp 1)) (wrong-type-argument err))",
+                &env
+            )
+            .unwrap(),
+            "(wrong-type-argument listp 1)"
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (error \"bad %s: %S\" \"input\" \"x\") (error (equal err '(error \"bad input: \\\"x\\\"\"))))",
+                &env
+            )
+            .unwrap(),
+            "t"
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (user-error \"no %d\" 3) (user-error (error-message-string err)))",
+                &env
+            )
+            .unwrap(),
+            "\"no 3\""
+        );
+        assert_eq!(
+            core::rep(
+                "(condition-case err (car 1) (error (error-message-string err)))",
+                &env
+            )
+            .unwrap(),
+            "\"(wrong-type-argument listp 1)\""
+        );
+
+        let err = core::rep("(signal 'my-error '(1))", &env).unwrap_err();
+        assert_eq!(format!("{:#}", err), "1:1: (my-error 1)");
+    }
+
+    #[test]
+    fn test_ignore_errors_unwind_protect() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(ignore-errors (car 1) 2)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(ignore-errors 1 2)", &env).unwrap(), "2");
+
+        core::rep("(setq cleaned 0)", &env).unwrap();
+        assert_eq!(
+            core::rep("(unwind-protect 1 (setq cleaned (+ cleaned 1)))", &env).unwrap(),
+            "1"
+        );
+        assert!(core::rep(
+            "(unwind-protect (car 1) (setq cleaned (+ cleaned 1)))",
+            &env
+        )
+        .is_err());
+        assert_eq!(
+            core::rep(
+                "(catch 'done (unwind-protect (throw 'done 1) (setq cleaned (+ cleaned 1))))",
+                &env
+            )
+            .unwrap(),
+            "1"
+        );
+        assert_eq!(core::rep("cleaned", &env).unwrap(), "3");
+    }
+
+    #[test]
+    fn test_format() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(format \"plain\")", "\"plain\""),
+            (
+                "(equal (format \"%s and %S\" \"a\" \"b\") \"a and \\\"b\\\"\")",
+                "t",
+            ),
+            ("(equal (format \"%s\" '(1 \"x\")) \"(1 \\\"x\\\")\")", "t"),
+            (
+                "(format \"%d %o %x %X\" 255 8 255 -255)",
+                "\"255 10 ff -FF\"",
+            ),
+            ("(format \"%d%%\" 2.7)", "\"2%\""),
+            ("(format \"%c\" 97)", "\"a\""),
+            ("(format \"%f %.2f\" 1 3.14159)", "\"1.000000 3.14\""),
+            ("(format \"%.3s\" \"abcdef\")", "\"abc\""),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        assert!(core::rep("(format \"%d\")", &env).is_err());
+        assert!(core::rep("(format \"%d\" \"x\")", &env).is_err());
+        assert!(core::rep("(format \"%q\" 1)", &env).is_err());
+        assert!(core::rep("(format 1)", &env).is_err());
+    }
+
+    #[test]
+    fn test_print() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(princ \"a\\nb\")", "\"a\\nb\""),
+            ("(prin1 '(1 \"x\"))", "(1 \"x\")"),
+            ("(print 'a)", "a"),
+            ("(message \"%d items\" 3)", "\"3 items\""),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        assert!(core::rep("(message 1)", &env).is_err());
+        assert!(core::rep("(princ)", &env).is_err());
+    }
+
+    #[test]
+    fn test_defmacro() {
+        let env = core::default_env();
+        assert_eq!(
+            core::rep(
+                "(defmacro my-unless (cond &rest body) (list 'if cond nil (cons 'progn body)))",
+                &env
+            )
+            .unwrap(),
+            "my-unless"
+        );
+        assert_eq!(core::rep("(my-unless nil 1 2)", &env).unwrap(), "2");
+        assert_eq!(core::rep("(my-unless t (undefined))", &env).unwrap(), "nil");
+
+        // arguments reach the expander unevaluated
+        core::rep("(defmacro my-quote (x) (list 'quote x))", &env).unwrap();
+        assert_eq!(core::rep("(my-quote (a b))", &env).unwrap(), "(a b)");
+
+        // the expansion is evaluated in the caller's environment
+        core::rep("(defmacro my-inc (v) (list 'setq v (list '+ v 1)))", &env).unwrap();
+        assert_eq!(
+            core::rep("(let ((n 1)) (my-inc n) (my-inc n) n)", &env).unwrap(),
+            "3"
+        );
+
+        // errors in generated code point at the call
+        core::rep("(defmacro broken () (list 'car 1))", &env).unwrap();
+        let err = core::rep("(list\n (broken))", &env).unwrap_err();
+        assert_eq!(format!("{:#}", err), "2:2: (wrong-type-argument listp 1)");
+
+        assert!(core::rep("(funcall 'my-inc 'x)", &env).is_err());
+        assert!(core::rep("(my-quote)", &env).is_err());
+    }
+
+    #[test]
+    fn test_macroexpand() {
+        let env = core::default_env();
+        core::rep("(defmacro my-inc (v) (list 'setq v (list '+ v 1)))", &env).unwrap();
+        core::rep(
+            "(defmacro inc-twice (v) (list 'progn (list 'my-inc v) (list 'my-inc v)))",
+            &env,
+        )
+        .unwrap();
+
+        for (input, expected) in [
+            ("(macroexpand-1 '(my-inc x))", "(setq x (+ x 1))"),
+            ("(macroexpand-1 '(car x))", "(car x)"),
+            ("(macroexpand-1 'x)", "x"),
+            (
+                "(macroexpand-1 '(inc-twice x))",
+                "(progn (my-inc x) (my-inc x))",
+            ),
+            (
+                "(macroexpand '(inc-twice x))",
+                "(progn (my-inc x) (my-inc x))",
+            ),
+            (
+                "(macroexpand-all '(inc-twice x))",
+                "(progn (setq x (+ x 1)) (setq x (+ x 1)))",
+            ),
+            (
+                "(macroexpand-all '(list '(my-inc x)))",
+                "(list '(my-inc x))",
+            ),
+            // only the unquoted parts of a template are code
+            (
+                "(macroexpand-all '`(my-inc ,(my-inc x) ,@(my-inc y) . ,(my-inc z)))",
+                "`(my-inc ,(setq x (+ x 1)) ,@(setq y (+ y 1)) . ,(setq z (+ z 1)))",
+            ),
+            (
+                "(macroexpand-all '`(a `(b ,(my-inc y) ,,(my-inc x))))",
+                "`(a `(b ,(my-inc y) ,,(setq x (+ x 1))))",
+            ),
+            (
+                "(macroexpand-all '(let ((my-inc (my-inc y))) my-inc))",
+                "(let ((my-inc (setq y (+ y 1)))) my-inc)",
+            ),
+            (
+                "(macroexpand-all '(cond ((my-inc a) (my-inc b))))",
+                "(cond ((setq a (+ a 1)) (setq b (+ b 1))))",
+            ),
+            (
+                "(macroexpand-all '(lambda (my-inc) (my-inc z)))",
+                "(lambda (my-inc) (setq z (+ z 1)))",
+            ),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_backquote() {
+        let env = core::default_env();
+        core::rep("(setq x 1)", &env).unwrap();
+        core::rep("(setq l '(2 3))", &env).unwrap();
+
+        for (input, expected) in [
+            ("`x", "x"),
+            ("`,x", "1"),
+            ("`(a ,x ,@l b)", "(a 1 2 3 b)"),
+            ("`(,@l)", "(2 3)"),
+            ("`(1 ,@nil 2)", "(1 2)"),
+            ("`(a . ,x)", "(a . 1)"),
+            ("`(a (b ,(+ x 1)))", "(a (b 2))"),
+            ("`(a `(b ,(c ,x)))", "(a `(b ,(c 1)))"),
+            ("`(a `(b ,@,@l))", "(a `(b ,@2 ,@3))"),
+            ("`(a `(b ,,@l))", "(a `(b ,2 ,3))"),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+        assert!(core::rep("`(,@x)", &env).is_err());
+
+        core::rep(
+            "(defmacro my-when (cond &rest body) `(if ,cond (progn ,@body)))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(core::rep("(my-when t 1 2)", &env).unwrap(), "2");
+        assert_eq!(
+            core::rep("(macroexpand-1 '(my-when t 1 2))", &env).unwrap(),
+            "(if t (progn 1 2))"
+        );
+    }
+
+    #[test]
+    fn test_bignum() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(* 9999999999 9999999999)", "99999999980000000001"),
+            ("(+ 9223372036854775807 1)", "9223372036854775808"),
+            ("(- -9223372036854775808 1)", "-9223372036854775809"),
+            ("(/ -9223372036854775808 -1)", "9223372036854775808"),
+            (
+                "123456789012345678901234567890",
+                "123456789012345678901234567890",
+            ),
+            (
+                "-123456789012345678901234567890",
+                "-123456789012345678901234567890",
+            ),
+            ("(- 100000000000000000000 99999999999999999999)", "1"),
+            ("(/ 100000000000000000000 10)", "10000000000000000000"),
+            ("(= 100000000000000000000 100000000000000000000)", "t"),
+            ("(< -100000000000000000000 0 100000000000000000000)", "t"),
+            ("(> 100000000000000000000 1.5)", "t"),
+            (
+                "(equal 100000000000000000000 (* 10000000000 10000000000))",
+                "t",
+            ),
+            (
+                "(format \"%d %x\" 100000000000000000000 -100000000000000000000)",
+                "\"100000000000000000000 -56bc75e2d63100000\"",
+            ),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        // results that fit again are plain fixnums
+        assert_eq!(
+            core::rep(
+                "(nth (- (+ 9223372036854775807 2) 9223372036854775807) '(a b c))",
+                &env
+            )
+            .unwrap(),
+            "c"
+        );
+        assert!(core::rep("(nth 100000000000000000000 '(a))", &env).is_err());
+    }
+
+    #[test]
+    fn test_ratio() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(/ 1 3)", "1/3"),
+            ("(/ 6 4)", "3/2"),
+            ("(/ -6 4)", "-3/2"),
+            ("(/ 6 -3)", "-2"),
+            ("(/ 1 3 2)", "1/6"),
+            ("(+ 1/3 1/6)", "1/2"),
+            ("(+ 1/3 2/3)", "1"),
+            ("(* 2/3 3/2)", "1"),
+            ("(- 1/2 1)", "-1/2"),
+            ("(/ 1/2 1/4)", "2"),
+            ("(* 1/3 100000000000000000000)", "100000000000000000000/3"),
+            ("(* 1/3 300000000000000000000)", "100000000000000000000"),
+            // floats are contagious
+            ("(+ 1/2 0.25)", "0.75"),
+            ("(/ 1.0 4)", "0.25"),
+            ("(= 1/2 0.5)", "t"),
+            ("(< 1/3 1/2 1)", "t"),
+            ("(< 1/2 1/3)", "nil"),
+            ("(= 2/4 1/2)", "t"),
+            ("(equal (/ 1 3) 1/3)", "t"),
+            ("(numerator 6/4)", "3"),
+            ("(denominator 6/4)", "2"),
+            ("(numerator -5)", "-5"),
+            ("(denominator -5)", "1"),
+            ("(format \"%d %s\" -7/2 7/2)", "\"-3 7/2\""),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        let err = core::rep("(numerator 0.5)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongTypeArgument {
+                expected: "rationalp".into(),
+                actual: types::RuspExp::Atom(types::RuspAtom::Float(0.5))
+            }
+        );
+        let err = core::rep("(/ 1/2 0)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::ArithError
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(multiple-value-list (floor 7 2))", "(3 1)"),
+            ("(multiple-value-list (floor -7 2))", "(-4 1)"),
+            ("(multiple-value-list (ceiling 7 2))", "(4 -1)"),
+            ("(multiple-value-list (truncate -7 2))", "(-3 -1)"),
+            ("(multiple-value-list (round 7 2))", "(4 -1)"),
+            ("(multiple-value-list (round 5 2))", "(2 1)"),
+            ("(multiple-value-list (round -5 2))", "(-2 -1)"),
+            ("(multiple-value-list (floor 7/2))", "(3 1/2)"),
+            ("(multiple-value-list (floor -7/2))", "(-4 1/2)"),
+            ("(multiple-value-list (round 5/2))", "(2 1/2)"),
+            ("(multiple-value-list (floor 2.5))", "(2 0.5)"),
+            ("(multiple-value-list (ceiling 2.5))", "(3 -0.5)"),
+            ("(multiple-value-list (round 2.5))", "(2 0.5)"),
+            ("(multiple-value-list (round 3.5))", "(4 -0.5)"),
+            ("(multiple-value-list (truncate -2.5))", "(-2 -0.5)"),
+            ("(multiple-value-list (floor 7 2.0))", "(3 1.0)"),
+            ("(multiple-value-list (floor 1/2 1/3))", "(1 1/6)"),
+            ("(floor 7 nil)", "7"),
+            ("(+ (floor 7 2) 1)", "4"),
+            ("(floor 100000000000000000000.0)", "100000000000000000000"),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        for input in ["(floor 1 0)", "(round 1.5 0.0)", "(truncate (/ 1.0 0))"] {
+            let err = core::rep(input, &env).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<types::RuspErr>().unwrap(),
+                &types::RuspErr::ArithError,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_numeric_functions() {
+        let env = core::default_env();
+        for (input, expected) in [
+            ("(% 7 3)", "1"),
+            ("(% -7 3)", "-1"),
+            ("(% 7 -3)", "1"),
+            ("(% -9223372036854775808 -1)", "0"),
+            ("(mod 7 3)", "1"),
+            ("(mod -7 3)", "2"),
+            ("(mod 7 -3)", "-2"),
+            ("(mod -7 -3)", "-1"),
+            ("(mod 7/2 1)", "1/2"),
+            ("(mod -5.5 2)", "0.5"),
+            ("(mod 5.5 -2)", "-0.5"),
+            ("(1+ 1)", "2"),
+            ("(1+ 9223372036854775807)", "9223372036854775808"),
+            ("(1- 1/2)", "-1/2"),
+            ("(1- 0.5)", "-0.5"),
+            ("(abs -3)", "3"),
+            ("(abs -9223372036854775808)", "9223372036854775808"),
+            ("(abs -1/2)", "1/2"),
+            ("(abs -2.5)", "2.5"),
+            ("(min 3 1 2)", "1"),
+            ("(max 3 1 2)", "3"),
+            ("(max 1 2.5 1/2)", "2.5"),
+            ("(min 1/2 0.75)", "1/2"),
+            ("(max 1 (/ 0.0 0) 2)", "+nan.0"),
+            ("(expt 2 10)", "1024"),
+            ("(expt 2 64)", "18446744073709551616"),
+            ("(expt 2 -2)", "1/4"),
+            ("(expt 2/3 2)", "4/9"),
+            ("(expt 0 0)", "1"),
+            ("(expt -1 9223372036854775807)", "-1"),
+            ("(expt 1/2 -3)", "8"),
+            ("(expt 4 0.5)", "2.0"),
+            ("(expt 2.0 3)", "8.0"),
+            ("(sqrt 2.25)", "1.5"),
+            ("(sqrt -1)", "+nan.0"),
+            ("(exp 0)", "1.0"),
+            ("(log 1)", "0.0"),
+            ("(log 8 2)", "3.0"),
+            ("(log 1000 10)", "3.0"),
+            ("(log 0)", "-inf.0"),
+            ("(sin 0)", "0.0"),
+            ("(cos 0)", "1.0"),
+            ("(tan 0)", "0.0"),
+            ("(atan 1 0)", "1.5707963267948966"),
+            ("(= (* 4 (atan 1)) (atan 0 -1))", "t"),
+            ("(logand 12 10)", "8"),
+            ("(logand)", "-1"),
+            ("(logior 12 10)", "14"),
+            ("(logxor 12 10)", "6"),
+            ("(logand -1 100000000000000000000)", "100000000000000000000"),
+            ("(ash 1 70)", "1180591620717411303424"),
+            ("(ash 5 -1)", "2"),
+            ("(ash -5 -1)", "-3"),
+            ("(ash 0 9223372036854775807)", "0"),
+            ("(ash 1 -9223372036854775808)", "0"),
+            ("(float 1/4)", "0.25"),
+            ("(float 2.5)", "2.5"),
+            ("(truncate 2.5)", "2"),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+
+        for input in [
+            "(ash 1 9223372036854775807)",
+            "(ash -1 100000000)",
+            "(expt 2 100000000)",
+            "(expt 10 -100000000)",
+            "(expt 1/3 100000000)",
+        ] {
+            let err = core::rep(input, &env).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<types::RuspErr>().unwrap(),
+                &types::RuspErr::OverflowError,
+                "input: {}",
+                input
+            );
+        }
+        for input in ["(% 1 0)", "(mod 1 0)", "(mod 1/2 0)", "(expt 0 -1)"] {
+            let err = core::rep(input, &env).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<types::RuspErr>().unwrap(),
+                &types::RuspErr::ArithError,
+                "input: {}",
+                input
+            );
+        }
+        for (input, expected) in [
+            ("(abs 'a)", "number-or-marker-p"),
+            ("(sqrt \"4\")", "number-or-marker-p"),
+            ("(max 1 nil)", "number-or-marker-p"),
+            ("(% 1.5 1)", "integer-or-marker-p"),
+            ("(logand 1 1/2)", "integer-or-marker-p"),
+        ] {
+            let err = core::rep(input, &env).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref(),
+                    Some(types::RuspErr::WrongTypeArgument { expected: e, .. }) if e == expected
+                ),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_random() {
+        let env = core::default_env();
+        let seeded = |seed: &str| {
+            core::rep(&format!("(random \"{}\")", seed), &env).unwrap();
+            core::rep("(list (random 100) (random 100) (random))", &env).unwrap()
+        };
+        assert_eq!(seeded("rusp"), seeded("rusp"));
+        assert_ne!(seeded("rusp"), seeded("lisp"));
+
+        core::rep("(random t)", &env).unwrap();
+        for _ in 0..100 {
+            let i: i64 = core::rep("(random 10)", &env).unwrap().parse().unwrap();
+            assert!((0..10).contains(&i));
+        }
+        assert_eq!(core::rep("(random 1)", &env).unwrap(), "0");
+        assert!(core::rep("(random 1.5)", &env).is_err());
+    }
+
+    #[test]
+    fn test_multiple_values() {
+        let env = core::default_env();
+        core::rep("(defun divide (a b) (floor a b))", &env).unwrap();
+        for (input, expected) in [
+            ("(values 1 2 3)", "1"),
+            ("(values)", "nil"),
+            ("(multiple-value-list (values 1 2 3))", "(1 2 3)"),
+            ("(multiple-value-list (values))", "nil"),
+            ("(multiple-value-list 5)", "(5)"),
+            ("(multiple-value-list (progn (values 1 2)))", "(1 2)"),
+            ("(multiple-value-list (let ((x 1)) (values x 2)))", "(1 2)"),
+            ("(multiple-value-list (divide 7 2))", "(3 1)"),
+            // only values in tail position are passed on
+            ("(multiple-value-list (list (values 1 2)))", "((1))"),
+            ("(multiple-value-list (progn (values 1 2) 1))", "(1)"),
+            ("(multiple-value-list (+ (values 1 2)))", "(1)"),
+            (
+                "(multiple-value-bind (q r) (floor 7 2) (list q r))",
+                "(3 1)",
+            ),
+            (
+                "(multiple-value-bind (a b c) (values 1 2) (list a b c))",
+                "(1 2 nil)",
+            ),
+            ("(multiple-value-bind (a) (values 1 2) a)", "1"),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_arith_error() {
+        let env = core::default_env();
+        for input in [
+            "(/ 1 0)",
+            "(/ 0 0)",
+            "(/ 10 2 0)",
+            "(/ 100000000000000000000 0)",
+        ] {
+            let err = core::rep(input, &env).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<types::RuspErr>().unwrap(),
+                &types::RuspErr::ArithError,
+                "input: {}",
+                input
+            );
+        }
+        for (input, expected) in [
+            (
+                "(condition-case nil (/ 1 0) (arith-error 'caught))",
+                "caught",
+            ),
+            ("(condition-case err (/ 1 0) (error err))", "(arith-error)"),
+            (
+                "(condition-case err (ash 1 100000000) (arith-error err))",
+                "(overflow-error)",
+            ),
+            ("(ignore-errors (/ 1 0))", "nil"),
+            // floats follow IEEE rules instead of signaling
+            ("(/ 1.0 0)", "+inf.0"),
+            ("(/ -1 0.0)", "-inf.0"),
+            ("(/ 0.0 0)", "+nan.0"),
+            ("(let ((nan (/ 0.0 0))) (= nan nan))", "nil"),
+            ("(< 100000000000000000000 (/ 1.0 0))", "t"),
+            ("(+ 1.0 1.0)", "2.0"),
+            ("(* 1.5 2)", "3.0"),
+        ] {
+            assert_eq!(
+                core::rep(input, &env).unwrap(),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_cons_car_cdr() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(cons 1 2)", &env).unwrap(), "(1 . 2)");
+        assert_eq!(core::rep("(cons 1 '(2))", &env).unwrap(), "(1 2)");
+        assert_eq!(core::rep("(car '(1 2))", &env).unwrap(), "1");
+        assert_eq!(core::rep("(cdr '(1 2))", &env).unwrap(), "(2)");
+        assert_eq!(core::rep("(car nil)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(cdr nil)", &env).unwrap(), "nil");
+
+        let err = core::rep("(car 1)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongTypeArgument {
+                expected: "listp".into(),
+                actual: types::RuspExp::Atom(types::RuspAtom::Int(1))
+            }
+        );
+    }
+
+    #[test]
+    fn test_list_functions() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(list)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(list 1 (+ 1 1) 'a)", &env).unwrap(), "(1 2 a)");
+        assert_eq!(core::rep("(append)", &env).unwrap(), "nil");
+        assert_eq!(
+            core::rep("(append '(1 2) nil '(3) '(4 5))", &env).unwrap(),
+            "(1 2 3 4 5)"
+        );
+        assert_eq!(core::rep("(append '(1) 2)", &env).unwrap(), "(1 . 2)");
+        assert!(core::rep("(append 1 '(2))", &env).is_err());
+        assert_eq!(core::rep("(nth 1 '(a b c))", &env).unwrap(), "b");
+        assert_eq!(core::rep("(nth 5 '(a b c))", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(nthcdr 2 '(a b c))", &env).unwrap(), "(c)");
+        assert_eq!(core::rep("(nthcdr 0 '(a b c))", &env).unwrap(), "(a b c)");
+        assert_eq!(core::rep("(length '(a b c))", &env).unwrap(), "3");
+        assert_eq!(core::rep("(length nil)", &env).unwrap(), "0");
+        assert_eq!(core::rep("(length \"abc\")", &env).unwrap(), "3");
+        assert!(core::rep("(length '(1 . 2))", &env).is_err());
+        assert_eq!(core::rep("(reverse '(1 2 3))", &env).unwrap(), "(3 2 1)");
+        assert_eq!(core::rep("(last '(1 2 3))", &env).unwrap(), "(3)");
+        assert_eq!(core::rep("(last nil)", &env).unwrap(), "nil");
+    }
+
+    #[test]
+    fn test_member_assoc() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(member 2 '(1 2 3))", &env).unwrap(), "(2 3)");
+        assert_eq!(
+            core::rep("(member '(1) '(a (1) b))", &env).unwrap(),
+            "((1) b)"
+        );
+        assert_eq!(core::rep("(member 4 '(1 2 3))", &env).unwrap(), "nil");
+
+        core::rep("(setq alist '((a . 1) (\"b\" . 2) ((c) . 3)))", &env).unwrap();
+        assert_eq!(core::rep("(assoc 'a alist)", &env).unwrap(), "(a . 1)");
+        assert_eq!(
+            core::rep("(assoc \"b\" alist)", &env).unwrap(),
+            "(\"b\" . 2)"
+        );
+        assert_eq!(core::rep("(assoc '(c) alist)", &env).unwrap(), "((c) . 3)");
+        assert_eq!(core::rep("(assq 'a alist)", &env).unwrap(), "(a . 1)");
+        assert_eq!(core::rep("(assq '(c) alist)", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(assq 'z alist)", &env).unwrap(), "nil");
+    }
+
+    #[test]
+    fn test_eq_equal() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(eq 'a 'a)", &env).unwrap(), "t");
+        assert_eq!(core::rep("(eq '(1) '(1))", &env).unwrap(), "nil");
+        assert_eq!(core::rep("(equal '(1) '(1))", &env).unwrap(), "t");
+        assert_eq!(core::rep("(consp '(1))", &env).unwrap(), "t");
+        assert_eq!(core::rep("(listp nil)", &env).unwrap(), "t");
+        assert_eq!(core::rep("(atom 1)", &env).unwrap(), "t");
+    }
+
+    #[test]
+    fn test_apply_funcall() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(funcall '+ 1 2)", &env).unwrap(), "3");
+        assert_eq!(
+            core::rep("(funcall (lambda (x) (* x x)) 3)", &env).unwrap(),
+            "9"
+        );
+        assert_eq!(core::rep("(funcall 'car '(1 2))", &env).unwrap(), "1");
+        assert_eq!(core::rep("(apply '+ 1 2 '(3 4))", &env).unwrap(), "10");
+        assert_eq!(core::rep("(apply 'list '(a b))", &env).unwrap(), "(a b)");
+        assert_eq!(core::rep("(apply (lambda (x) x) '(a))", &env).unwrap(), "a");
+        assert!(core::rep("(funcall 1)", &env).is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let env = core::default_env();
+        core::rep("(defun square (x) (* x x))", &env).unwrap();
+        assert_eq!(
+            core::rep("(mapcar 'square '(1 2 3))", &env).unwrap(),
+            "(1 4 9)"
+        );
+        assert_eq!(
+            core::rep("(mapcar 'car '((a 1) (b 2)))", &env).unwrap(),
+            "(a b)"
+        );
+        assert_eq!(core::rep("(mapcar 'square nil)", &env).unwrap(), "nil");
+
+        core::rep("(setq sum 0)", &env).unwrap();
+        assert_eq!(
+            core::rep("(mapc (lambda (x) (setq sum (+ sum x))) '(1 2 3))", &env).unwrap(),
+            "(1 2 3)"
+        );
+        assert_eq!(core::rep("sum", &env).unwrap(), "6");
+
+        assert_eq!(
+            core::rep("(mapcan (lambda (x) (list x x)) '(1 2))", &env).unwrap(),
+            "(1 1 2 2)"
+        );
+    }
+
+    #[test]
+    fn test_filter_reduce() {
+        let env = core::default_env();
+        core::rep("(defun small (x) (< x 3))", &env).unwrap();
+        assert_eq!(
+            core::rep("(seq-filter 'small '(1 2 3 4))", &env).unwrap(),
+            "(1 2)"
+        );
+        assert_eq!(
+            core::rep("(remove-if 'small '(1 2 3 4))", &env).unwrap(),
+            "(3 4)"
+        );
+
+        assert_eq!(core::rep("(reduce '+ '(1 2 3 4))", &env).unwrap(), "10");
+        assert_eq!(core::rep("(reduce '+ '(1 2 3 4) 10)", &env).unwrap(), "20");
+        assert_eq!(core::rep("(reduce '+ nil)", &env).unwrap(), "0");
+        assert_eq!(core::rep("(reduce '+ nil 5)", &env).unwrap(), "5");
+        assert_eq!(
+            core::rep("(reduce (lambda (acc x) (cons x acc)) '(1 2 3) nil)", &env).unwrap(),
+            "(3 2 1)"
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        let env = core::default_env();
+        assert_eq!(core::rep("(sort '(3 1 2) '<)", &env).unwrap(), "(1 2 3)");
+        assert_eq!(core::rep("(sort nil '<)", &env).unwrap(), "nil");
+        assert_eq!(
+            core::rep(
+                "(sort '((1 . a) (0 . b) (1 . c) (0 . d)) (lambda (x y) (< (car x) (car y))))",
+                &env
+            )
+            .unwrap(),
+            "((0 . b) (0 . d) (1 . a) (1 . c))"
+        );
+        assert!(core::rep("(sort '(1 a) '<)", &env).is_err());
+    }
+}
@@ -3,13 +3,54 @@ use anyhow::Context;
 use crate::builtin;
 use crate::reader;
 use crate::types;
-use crate::util;
 
-pub fn default_env<'a>() -> types::RuspEnv<'a> {
-    let mut env = types::RuspEnv::default();
+const DEFAULT_MAX_LISP_EVAL_DEPTH: i64 = 1600;
+// `max-lisp-eval-depth` is never taken to be lower than this, so that the
+// forms that raise it again can still run.
+const MIN_MAX_LISP_EVAL_DEPTH: i64 = 100;
+// The stack a thread is assumed to have unless `set_stack_size` says
+// otherwise; Rust gives spawned threads this much by default.
+const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+// Stack left unused below the deepest `eval` for the builtins it calls.
+const STACK_RESERVE: usize = 256 * 1024;
 
-    env.variable.insert("nil".to_string(), types::nil!());
-    env.variable.insert("t".to_string(), types::t!());
+thread_local! {
+    static EVAL_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    // The global environment and stack position of the outermost `eval`
+    // running on this thread.
+    static EVAL_ROOT: std::cell::RefCell<Option<(types::RuspEnv, usize)>> =
+        const { std::cell::RefCell::new(None) };
+    static STACK_SIZE: std::cell::Cell<usize> = const { std::cell::Cell::new(DEFAULT_STACK_SIZE) };
+    // All values of the last `values` call, along with the eval depth of the
+    // form that made it and its primary value.
+    static MULTIPLE_VALUES: std::cell::RefCell<Option<(usize, types::RuspExp, Vec<types::RuspExp>)>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+pub fn default_env() -> types::RuspEnv {
+    let env = types::RuspEnv::default();
+
+    env.define_variable("nil", types::nil!());
+    env.define_variable("t", types::t!());
+    env.define_variable(
+        "max-lisp-eval-depth",
+        types::RuspExp::Atom(types::RuspAtom::Int(DEFAULT_MAX_LISP_EVAL_DEPTH)),
+    );
+
+    types::rusp_special!(
+        env,
+        ("if", builtin::if_),
+        ("cond", builtin::cond),
+        ("when", builtin::when),
+        ("unless", builtin::unless),
+        ("and", builtin::and),
+        ("or", builtin::or),
+        ("progn", builtin::progn),
+        ("let", builtin::let_),
+        ("let*", builtin::let_star),
+        ("condition-case", builtin::condition_case),
+        ("multiple-value-bind", builtin::multiple_value_bind),
+    );
 
     types::rusp_func!(
         env,
@@ -23,133 +64,942 @@ pub fn default_env<'a>() -> types::RuspEnv<'a> {
         (">=", builtin::arith_gte),
         ("=", builtin::arith_eq),
         ("!=", builtin::arith_neq),
-        ("if", builtin::if_),
+        ("numerator", builtin::numerator),
+        ("denominator", builtin::denominator),
+        ("floor", builtin::floor),
+        ("ceiling", builtin::ceiling),
+        ("round", builtin::round),
+        ("truncate", builtin::truncate),
+        ("%", builtin::rem),
+        ("mod", builtin::mod_),
+        ("1+", builtin::one_plus),
+        ("1-", builtin::one_minus),
+        ("abs", builtin::abs),
+        ("min", builtin::min),
+        ("max", builtin::max),
+        ("expt", builtin::expt),
+        ("sqrt", builtin::sqrt),
+        ("exp", builtin::exp),
+        ("log", builtin::log),
+        ("sin", builtin::sin),
+        ("cos", builtin::cos),
+        ("tan", builtin::tan),
+        ("atan", builtin::atan),
+        ("logand", builtin::logand),
+        ("logior", builtin::logior),
+        ("logxor", builtin::logxor),
+        ("ash", builtin::ash),
+        ("float", builtin::float),
+        ("random", builtin::random),
+        ("values", builtin::values),
+        ("multiple-value-list", builtin::multiple_value_list),
+        ("not", builtin::not),
+        ("null", builtin::not),
+        ("while", builtin::while_),
+        ("dotimes", builtin::dotimes),
+        ("dolist", builtin::dolist),
+        ("catch", builtin::catch),
+        ("throw", builtin::throw),
+        ("signal", builtin::signal),
+        ("error", builtin::error),
+        ("user-error", builtin::user_error),
+        ("ignore-errors", builtin::ignore_errors),
+        ("unwind-protect", builtin::unwind_protect),
+        ("error-message-string", builtin::error_message_string),
+        ("format", builtin::format),
+        ("princ", builtin::princ),
+        ("prin1", builtin::prin1),
+        ("print", builtin::print),
+        ("message", builtin::message),
         ("set", builtin::set),
         ("setq", builtin::setq),
+        ("defun", builtin::defun_),
+        ("defmacro", builtin::defmacro),
+        ("macroexpand-1", builtin::macroexpand_1),
+        ("macroexpand", builtin::macroexpand),
+        ("macroexpand-all", builtin::macroexpand_all),
+        ("defvar", builtin::defvar),
+        ("defconst", builtin::defconst),
+        ("fset", builtin::fset),
+        ("symbol-function", builtin::symbol_function),
+        ("eq", builtin::eq),
+        ("equal", builtin::equal),
+        ("atom", builtin::atom),
+        ("consp", builtin::consp),
+        ("listp", builtin::listp),
+        ("cons", builtin::cons),
+        ("car", builtin::car),
+        ("cdr", builtin::cdr),
+        ("list", builtin::list),
+        ("append", builtin::append),
+        ("nth", builtin::nth),
+        ("nthcdr", builtin::nthcdr),
+        ("length", builtin::length),
+        ("reverse", builtin::reverse),
+        ("last", builtin::last),
+        ("member", builtin::member),
+        ("assoc", builtin::assoc),
+        ("assq", builtin::assq),
         ("quote", builtin::quote),
+        ("backquote", builtin::backquote),
         ("lambda", builtin::lambda),
+        ("load", builtin::load),
         ("apply", builtin::apply),
+        ("funcall", builtin::funcall),
+        ("mapcar", builtin::mapcar),
+        ("mapc", builtin::mapc),
+        ("mapcan", builtin::mapcan),
+        ("seq-filter", builtin::seq_filter),
+        ("remove-if", builtin::remove_if),
+        ("reduce", builtin::reduce),
+        ("sort", builtin::sort),
     )
     // ("def", builtin::def),
     // ("fn", builtin::fn_func),
-    // ("let", builtin::let_func),
     // ("do", builtin::do_func),
     // ("eval", builtin::eval_func),
     // ("read", builtin::read_func),
     // ("print", builtin::print_func),
     // ("println", builtin::println_func),
-    // ("list", builtin::list_func),
-    // ("first", builtin::first_func),
-    // ("rest", builtin::rest_func),
-    // ("cons", builtin::cons_func),
     // ("concat", builtin::concat_func),
     // ("empty?", builtin::empty_func),
-    // ("count", builtin::count_func),
-    // ("apply", builtin::apply_func),
-    // ("map", builtin::map_func),
-    // ("filter", builtin::filter_func),
-    // ("load", builtin::load_func),
     // ("time", builtin::time_func),
     // ("exit", builtin::exit_func),
-    // ("throw", builtin::throw_func),
-    // ("try", builtin::try_func),
-    // ("catch", builtin::catch_func),
     // ("throw?", builtin::throwp_func)
 }
 
-fn read(x: &str) -> anyhow::Result<types::RuspExp> {
+fn read(x: &str) -> anyhow::Result<Vec<types::RuspExp>> {
     let mut reader = reader::Reader::new(x);
-    reader.read()
+    reader.read_all()
 }
 
-pub fn eval_lambda(
+// Bind `args` to the parameter list of a lambda in a new frame on top of
+// `env`. Parameters after `&optional` default to nil and the one after
+// `&rest` takes the remaining arguments as a list.
+fn bind_params(
+    params: &types::RuspExp,
+    args: Vec<types::RuspExp>,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspEnv> {
+    let new_env = types::RuspEnv::with_outer(env);
+    let actual = args.len();
+    let mut args = args.into_iter();
+    let mut required = 0;
+    let mut allowed = Some(0);
+    let mut optional = false;
+    let mut rest = false;
+    let mut missing = false;
+
+    for param in params {
+        let sym = param?.as_symbol()?;
+        match sym {
+            "&optional" => optional = true,
+            "&rest" => rest = true,
+            _ if rest => {
+                allowed = None;
+                new_env.define_variable(sym, args.by_ref().collect());
+            }
+            _ => {
+                if !optional {
+                    required += 1;
+                }
+                allowed = allowed.map(|n| n + 1);
+                let val = args.next();
+                missing |= val.is_none() && !optional;
+                new_env.define_variable(sym, val.unwrap_or(types::nil!()));
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        !missing && args.next().is_none(),
+        types::RuspErr::WrongNumberOfArguments {
+            required,
+            allowed,
+            actual,
+        }
+    );
+    Ok(new_env)
+}
+
+// Bind `args` and return the lambda body with its last form left in tail
+// position.
+fn apply_lambda(
     func: &types::RuspExp,
+    args: Vec<types::RuspExp>,
+) -> anyhow::Result<types::RuspTail> {
+    match func {
+        types::RuspExp::Atom(types::RuspAtom::Lambda {
+            params,
+            body,
+            env: lambda_env,
+        }) => eval_body_tail(body, &bind_params(params, args, lambda_env)?),
+        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
+            expected: "functionp".into(),
+            actual: func.clone()
+        })),
+    }
+}
+
+// Run a macro's expander on the unevaluated arguments of a call.
+fn expand_macro(
+    expander: &types::RuspExp,
     args: &types::RuspExp,
-    env: &mut types::RuspEnv,
 ) -> anyhow::Result<types::RuspExp> {
-    match func {
-        types::RuspExp::Atom(types::RuspAtom::Lambda { params, body }) => {
-            let symbols = params
-                .into_iter()
-                .map(|x_| {
-                    let x = x_?;
-                    match &**x {
-                        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => Ok(s),
-                        _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-                            expected: "symbol".into(),
-                            actual: x.to_string().into()
-                        })),
-                    }
-                })
-                .collect::<Result<Vec<_>, _>>()
-                .with_context(|| {
-                    anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-                        expected: "list<symbol>".into(),
-                        actual: params.to_string().into()
-                    })
-                })?;
-
-            let mut new_env = types::RuspEnv {
-                outer: Some(env),
-                ..Default::default()
+    resolve(apply_lambda(expander, args.to_vec()?)?)
+}
+
+// Expand `form` once if it is a macro call, or return None if it is not.
+pub fn macroexpand_1(
+    form: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<Option<types::RuspExp>> {
+    if let types::RuspExp::Cons { car, cdr } = form {
+        if let types::RuspExp::Atom(types::RuspAtom::Symbol(s)) = &**car {
+            if let Ok(types::RuspExp::Atom(types::RuspAtom::Macro(expander))) = &env.get_function(s)
+            {
+                return Ok(Some(expand_macro(expander, cdr)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Expand `form` until it is no longer a macro call.
+pub fn macroexpand(form: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<types::RuspExp> {
+    let mut form = form.clone();
+    while let Some(expanded) = macroexpand_1(&form, env)? {
+        form = expanded;
+    }
+    Ok(form)
+}
+
+// Expand the elements of `clause` from index `skip` onwards, leaving a clause
+// that is not a list alone.
+fn macroexpand_clause(
+    clause: &types::RuspExp,
+    skip: usize,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    if !clause.consp() {
+        return Ok(clause.clone());
+    }
+    clause
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let x = x?;
+            if i < skip {
+                return Ok((**x).clone());
+            }
+            macroexpand_all(x, env)
+        })
+        .collect()
+}
+
+// Expand the macro calls in the unquoted parts of a backquote template.
+// `depth` counts the enclosing backquotes as in `builtin::backquote`, so only
+// unquotes that bring it down to zero hold code.
+fn macroexpand_template(
+    x: &types::RuspExp,
+    depth: usize,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    for symbol in ["unquote", "unquote-splicing"] {
+        if let Some(arg) = builtin::form_arg(x, symbol) {
+            let arg = match depth {
+                1 => macroexpand_all(arg, env)?,
+                _ => macroexpand_template(arg, depth - 1, env)?,
             };
+            return Ok(builtin::wrap_form(symbol, arg));
+        }
+    }
+    if let Some(arg) = builtin::form_arg(x, "backquote") {
+        let arg = macroexpand_template(arg, depth + 1, env)?;
+        return Ok(builtin::wrap_form("backquote", arg));
+    }
+
+    match x {
+        types::RuspExp::Cons { car, cdr } => Ok(types::RuspExp::Cons {
+            car: std::sync::Arc::new(macroexpand_template(car, depth, env)?),
+            cdr: std::sync::Arc::new(macroexpand_template(cdr, depth, env)?),
+        }),
+        _ => Ok(x.clone()),
+    }
+}
+
+// Expand every macro call in `form`, including subforms. Quoted data,
+// backquote templates, lambda lists and the binding parts of the built-in
+// special forms are left alone.
+pub fn macroexpand_all(
+    form: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    let form = macroexpand(form, env)?;
+    let (car, cdr) = match &form {
+        types::RuspExp::Cons { car, cdr } => (car, cdr),
+        _ => return Ok(form),
+    };
+    let head = match &**car {
+        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) => s.as_str(),
+        _ => "",
+    };
+    if head == "quote" {
+        return Ok(form);
+    }
+    if head == "backquote" {
+        return macroexpand_template(&form, 0, env);
+    }
 
-            for elm in util::safe_zip_eq(symbols, args.into_iter()) {
-                let (sym, val_) = elm?;
-                let val = val_?;
-                new_env
-                    .variable
-                    .insert(sym.to_string(), eval(val, &mut env.clone())?);
+    let args = cdr
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let x = x?;
+            match (head, i) {
+                ("lambda" | "condition-case" | "multiple-value-bind", 0)
+                | ("defun" | "defmacro", 0 | 1) => Ok((**x).clone()),
+                ("let" | "let*", 0) => x
+                    .into_iter()
+                    .map(|binding| macroexpand_clause(binding?, 1, env))
+                    .collect(),
+                ("cond", _) => macroexpand_clause(x, 0, env),
+                ("condition-case", _) if i >= 2 => macroexpand_clause(x, 1, env),
+                ("dolist" | "dotimes", 0) => macroexpand_clause(x, 1, env),
+                _ => macroexpand_all(x, env),
             }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(std::iter::once((**car).clone()).chain(args).collect())
+}
 
-            eval(body, &mut new_env)
+// Call `func` with already evaluated `args`.  Builtins receive their
+// arguments unevaluated, so each value is quoted before being passed on.
+pub fn funcall(
+    func: &types::RuspExp,
+    args: Vec<types::RuspExp>,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspExp> {
+    match func {
+        types::RuspExp::Atom(types::RuspAtom::Lambda { .. }) => resolve(apply_lambda(func, args)?),
+        types::RuspExp::Atom(types::RuspAtom::Func(f)) => {
+            let quoted = args
+                .into_iter()
+                .map(|x| {
+                    [
+                        types::RuspExp::Atom(types::RuspAtom::Symbol("quote".to_string())),
+                        x,
+                    ]
+                    .into_iter()
+                    .collect::<types::RuspExp>()
+                })
+                .collect::<types::RuspExp>();
+            f(&quoted, env)
+        }
+        types::RuspExp::Atom(types::RuspAtom::Symbol(s)) if func.non_nil_p() => {
+            funcall(&env.get_function(s)?, args, env)
         }
         _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-            expected: "lambda".into(),
-            actual: func.to_string().into()
+            expected: "functionp".into(),
+            actual: func.clone()
         })),
     }
 }
 
-pub fn eval(x: &types::RuspExp, env: &mut types::RuspEnv) -> anyhow::Result<types::RuspExp> {
+pub fn eval_body(body: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<types::RuspExp> {
+    resolve(eval_body_tail(body, env)?)
+}
+
+pub fn eval_body_tail(
+    body: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<types::RuspTail> {
+    let mut iter = body.into_iter().peekable();
+    while let Some(x) = iter.next() {
+        let x = x?;
+        if iter.peek().is_none() {
+            return Ok(types::RuspTail::Eval((**x).clone(), env.clone()));
+        }
+        eval(x, env)?;
+    }
+    Ok(types::RuspTail::Value(types::nil!()))
+}
+
+fn resolve(tail: types::RuspTail) -> anyhow::Result<types::RuspExp> {
+    match tail {
+        types::RuspTail::Value(x) => Ok(x),
+        types::RuspTail::Eval(x, env) => eval(&x, &env),
+    }
+}
+
+// Evaluate one step of `x`.  Special forms and lambda bodies do not
+// evaluate their tail form themselves, so that `eval` can loop on it
+// instead of growing the Rust stack.
+fn eval_step(x: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<types::RuspTail> {
     match x {
         types::RuspExp::Atom(atom) => match atom {
-            types::RuspAtom::Symbol(s) => Ok(env.get_variable(s)?.clone()),
-            _ => Ok(x.clone()),
+            types::RuspAtom::Symbol(s) => Ok(types::RuspTail::Value(env.get_variable(s)?)),
+            _ => Ok(types::RuspTail::Value(x.clone())),
         },
-        types::RuspExp::Cons { ref car, ref cdr } => || -> anyhow::Result<types::RuspExp> {
+        types::RuspExp::Cons { car, cdr } => || -> anyhow::Result<types::RuspTail> {
             if let types::RuspExp::Atom(types::RuspAtom::Symbol(s)) = &**car {
                 let func = env.get_function(s)?;
-                return match *func {
-                    types::RuspExp::Atom(types::RuspAtom::Func(f)) => f(cdr, env),
+                return match &func {
+                    types::RuspExp::Atom(types::RuspAtom::Func(f)) => {
+                        Ok(types::RuspTail::Value(f(cdr, env)?))
+                    }
+                    types::RuspExp::Atom(types::RuspAtom::Special(f)) => f(cdr, env),
+                    types::RuspExp::Atom(types::RuspAtom::Lambda { .. }) => {
+                        apply_lambda(&func, eval_args(cdr, env)?)
+                    }
+                    types::RuspExp::Atom(types::RuspAtom::Macro(expander)) => {
+                        let expansion = expand_macro(expander, cdr)?;
+                        // errors in generated code point at the macro call
+                        if expansion.span().is_none() {
+                            if let Some(span) = x.span() {
+                                expansion.set_span(span);
+                            }
+                        }
+                        Ok(types::RuspTail::Eval(expansion, env.clone()))
+                    }
                     _ => Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-                        expected: "function".into(),
-                        actual: format!("{}", func).into()
+                        expected: "functionp".into(),
+                        actual: func.clone()
                     })),
                 };
             }
 
-            if let types::RuspExp::Cons{car: ref car_car, cdr: _} = &**car &&
+            if let types::RuspExp::Cons{car: car_car, cdr: _} = &**car &&
                 let types::RuspExp::Atom(types::RuspAtom::Symbol(s)) = &**car_car &&
                 s == "lambda" {
-                return eval_lambda(&eval(car, env)?, cdr, env);
+                return apply_lambda(&eval(car, env)?, eval_args(cdr, env)?);
             }
 
             Err(anyhow::anyhow!(types::RuspErr::WrongTypeArgument {
-                expected: "symbol".into(),
-                actual: format!("{}", car).into()
+                expected: "symbolp".into(),
+                actual: (**car).clone()
             }))
         }(),
     }
 }
 
+fn eval_args(args: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<Vec<types::RuspExp>> {
+    args.into_iter().map(|x| eval(x?, env)).collect()
+}
+
+// Tell the evaluator how much stack the current thread was spawned with, so
+// that deep recursion may use it before `excessive-lisp-nesting` is raised.
+pub fn set_stack_size(size: usize) {
+    STACK_SIZE.with(|x| x.set(size));
+}
+
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// Counts nested `eval` calls so that runaway recursion is reported as an
+// error before it exhausts the Rust stack: both `max-lisp-eval-depth` and
+// the stack actually used since the outermost `eval` are checked, as a frame
+// may take much more stack in an unoptimized build than in a release one.
+struct DepthGuard;
+
+impl DepthGuard {
+    fn enter(env: &types::RuspEnv) -> anyhow::Result<DepthGuard> {
+        let depth = EVAL_DEPTH.with(|x| x.get()) + 1;
+        let here = stack_position();
+        if depth == 1 {
+            EVAL_ROOT.with(|x| *x.borrow_mut() = Some((env.global(), here)));
+        }
+        // only the global binding counts, which saves walking the
+        // environment chain on every call
+        let (limit, used) = EVAL_ROOT.with(|x| {
+            let root = x.borrow();
+            let (global, base) = root.as_ref().unwrap();
+            let limit = global
+                .get_variable("max-lisp-eval-depth")
+                .and_then(|x| x.as_int())
+                .unwrap_or(DEFAULT_MAX_LISP_EVAL_DEPTH)
+                .max(MIN_MAX_LISP_EVAL_DEPTH);
+            (limit, base.abs_diff(here))
+        });
+        let available = STACK_SIZE.with(|x| x.get()).saturating_sub(STACK_RESERVE);
+        anyhow::ensure!(
+            depth as i64 <= limit && used <= available,
+            types::RuspErr::ExcessiveLispNesting { depth }
+        );
+        EVAL_DEPTH.with(|x| x.set(depth));
+        Ok(DepthGuard)
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        let depth = EVAL_DEPTH.with(|x| x.get()) - 1;
+        EVAL_DEPTH.with(|x| x.set(depth));
+        if depth == 0 {
+            let root = EVAL_ROOT.with(|x| x.borrow_mut().take());
+            drop(root);
+        }
+    }
+}
+
+pub fn eval(x: &types::RuspExp, env: &types::RuspEnv) -> anyhow::Result<types::RuspExp> {
+    if x.atom() {
+        return resolve(eval_step(x, env)?);
+    }
+
+    let _guard = DepthGuard::enter(env)?;
+    let mut tail = eval_step(x, env).map_err(|e| locate(e, x))?;
+    loop {
+        match tail {
+            types::RuspTail::Value(x) => return Ok(x),
+            types::RuspTail::Eval(x, env) => {
+                tail = eval_step(&x, &env).map_err(|e| locate(e, &x))?
+            }
+        }
+    }
+}
+
+// Return `values` from a builtin; the first one is its primary value, which
+// is all that callers other than `eval_values` see.
+pub fn values(values: Vec<types::RuspExp>) -> types::RuspExp {
+    let primary = values.first().cloned().unwrap_or(types::nil!());
+    let depth = EVAL_DEPTH.with(|x| x.get());
+    MULTIPLE_VALUES.with(|x| x.replace(Some((depth, primary.clone(), values))));
+    primary
+}
+
+// Evaluate `x` to all of its values. Only a `values` call made in tail
+// position of `x` counts; anything else yields just the primary value.
+pub fn eval_values(
+    x: &types::RuspExp,
+    env: &types::RuspEnv,
+) -> anyhow::Result<Vec<types::RuspExp>> {
+    MULTIPLE_VALUES.with(|x| x.take());
+    let depth = EVAL_DEPTH.with(|x| x.get()) + 1;
+    let value = eval(x, env)?;
+    match MULTIPLE_VALUES.with(|x| x.take()) {
+        Some((d, primary, values)) if d == depth && primary == value => Ok(values),
+        _ => Ok(vec![value]),
+    }
+}
+
+// Attach the span of `x` to an error that has none yet, so that it points at
+// the innermost form that was read from source.
+fn locate(e: anyhow::Error, x: &types::RuspExp) -> anyhow::Error {
+    if e.downcast_ref::<types::RuspSpan>().is_some() {
+        return e;
+    }
+    match x.span() {
+        Some(span) => e.context(span),
+        None => e,
+    }
+}
+
+pub fn load(path: &str, env: &types::RuspEnv) -> anyhow::Result<()> {
+    let src =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read file: {}", path))?;
+    // comment out a `#!` interpreter line so scripts can be run directly,
+    // keeping the same length so that positions stay put
+    let src = match src.strip_prefix("#!") {
+        Some(rest) => format!(";!{}", rest),
+        None => src,
+    };
+
+    for form in reader::Reader::with_file(&src, path).read_all()? {
+        eval(&form, env)?;
+    }
+    Ok(())
+}
+
 fn print(x: types::RuspExp) -> anyhow::Result<String> {
     Ok(x.to_string())
 }
 
-pub fn rep(mut x: &str, env: &mut types::RuspEnv) -> anyhow::Result<String> {
-    x = x.trim_start(); // simple skip whitespace
-    anyhow::ensure!(!x.is_empty(), types::RuspErr::ReplEmptyError);
+pub fn rep(x: &str, env: &types::RuspEnv) -> anyhow::Result<String> {
+    // simple skip whitespace; the reader still sees it so positions stay put
+    anyhow::ensure!(!x.trim_start().is_empty(), types::RuspErr::ReplEmptyError);
+
+    // every form is evaluated in turn; each result is printed on its own line
+    let mut results = Vec::new();
+    for form in read(x)? {
+        results.push(print(eval(&form, env)?)?);
+    }
+    Ok(results.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closure_adder() {
+        let env = default_env();
+        rep("(setq make-adder (lambda (n) (lambda (x) (+ x n))))", &env).unwrap();
+        rep("(setq add2 (apply make-adder '(2)))", &env).unwrap();
+        rep("(setq add10 (apply make-adder '(10)))", &env).unwrap();
+
+        assert_eq!(rep("(apply add2 '(40))", &env).unwrap(), "42");
+        assert_eq!(rep("(apply add10 '(40))", &env).unwrap(), "50");
+    }
+
+    #[test]
+    fn test_closure_outlives_frame() {
+        let env = default_env();
+        rep(
+            "(setq f ((lambda (captured) (lambda () captured)) 42))",
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(rep("(apply f nil)", &env).unwrap(), "42");
+        let err = rep("captured", &env).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            types::RuspErr::VoidVariable {
+                name: "captured".into()
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_closure_is_lexical() {
+        let env = default_env();
+        rep("(setq n 1)", &env).unwrap();
+        rep("(setq get-n ((lambda (n) (lambda () n)) 2))", &env).unwrap();
+        rep("(setq call-with-n (lambda (n) (apply get-n nil)))", &env).unwrap();
+
+        assert_eq!(rep("(apply get-n nil)", &env).unwrap(), "2");
+        assert_eq!(rep("(apply call-with-n '(3))", &env).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let env = default_env();
+        rep(
+            "(setq make-counter (lambda () ((lambda (count) (lambda () (setq count (+ count 1)))) 0)))",
+            &env,
+        )
+        .unwrap();
+        rep("(setq c1 (apply make-counter nil))", &env).unwrap();
+        rep("(setq c2 (apply make-counter nil))", &env).unwrap();
+
+        assert_eq!(rep("(apply c1 nil)", &env).unwrap(), "1");
+        assert_eq!(rep("(apply c1 nil)", &env).unwrap(), "2");
+        assert_eq!(rep("(apply c2 nil)", &env).unwrap(), "1");
+        assert!(rep("count", &env).is_err());
+    }
+
+    #[test]
+    fn test_closure_cycle_is_freed() {
+        let env = default_env();
+        rep(
+            "(defun make-loop () (let ((f nil)) (setq f (lambda () f))))",
+            &env,
+        )
+        .unwrap();
+        rep("(setq g (make-loop))", &env).unwrap();
+        let frame = match &env.get_variable("g").unwrap() {
+            types::RuspExp::Atom(types::RuspAtom::Lambda { env, .. }) => env.downgrade(),
+            _ => unreachable!(),
+        };
+
+        // a frame that is still reachable survives, along with its cycle
+        types::collect_cycles();
+        assert!(frame.upgrade().is_some());
+        // `f` would be void in an emptied frame
+        assert!(rep("(funcall (funcall g))", &env).is_ok());
 
-    print(eval(&read(x)?, env)?)
+        rep("(setq g nil)", &env).unwrap();
+        assert!(frame.upgrade().is_some());
+        types::collect_cycles();
+        assert!(frame.upgrade().is_none());
+
+        // closures in use elsewhere keep working across collections
+        rep("(setq make-adder (lambda (n) (lambda (x) (+ x n))))", &env).unwrap();
+        rep("(setq add2 (funcall make-adder 2))", &env).unwrap();
+        rep("(setq adders (list (make-loop) add2))", &env).unwrap();
+        assert_eq!(
+            rep(
+                "(progn (dotimes (i 5000) (let ((f nil)) (setq f (lambda () i)))) (funcall add2 1))",
+                &env
+            )
+            .unwrap(),
+            "3"
+        );
+        types::collect_cycles();
+        assert_eq!(rep("(funcall (nth 1 adders) 5)", &env).unwrap(), "7");
+        assert!(rep("(funcall (funcall (car adders)))", &env).is_ok());
+    }
+
+    #[test]
+    fn test_setq_updates_outer_binding() {
+        let env = default_env();
+        rep("(setq total 0)", &env).unwrap();
+        rep("(setq add (lambda (x) (setq total (+ total x))))", &env).unwrap();
+        rep("(apply add '(3))", &env).unwrap();
+        rep("(apply add '(4))", &env).unwrap();
+        assert_eq!(rep("total", &env).unwrap(), "7");
+
+        rep("(setq shadow (lambda (total) (setq total 100)))", &env).unwrap();
+        rep("(apply shadow '(1))", &env).unwrap();
+        assert_eq!(rep("total", &env).unwrap(), "7");
+
+        rep("(setq define-global (lambda () (set 'fresh 1)))", &env).unwrap();
+        rep("(apply define-global nil)", &env).unwrap();
+        assert_eq!(rep("fresh", &env).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_tail_call() {
+        let env = default_env();
+        rep(
+            "(defun count-down (n) (if (= n 0) 'done (count-down (- n 1))))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(rep("(count-down 1000000)", &env).unwrap(), "done");
+
+        rep(
+            "(defun sum-to (n acc) (cond ((= n 0) acc) (t (setq n (- n 1)) (sum-to n (+ acc n 1)))))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(rep("(sum-to 100000 0)", &env).unwrap(), "5000050000");
+
+        rep(
+            "(defun even (n) (or (= n 0) (and (> n 0) (let ((m (- n 1))) (odd m)))))",
+            &env,
+        )
+        .unwrap();
+        rep(
+            "(defun odd (n) (and (> n 0) (progn (when t (even (- n 1))))))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(rep("(even 100000)", &env).unwrap(), "t");
+        assert_eq!(rep("(odd 100000)", &env).unwrap(), "nil");
+    }
+
+    #[test]
+    fn test_max_lisp_eval_depth() {
+        let env = default_env();
+        rep("(defun runaway (n) (+ 1 (runaway n)))", &env).unwrap();
+        rep("(setq max-lisp-eval-depth 100)", &env).unwrap();
+
+        let err = rep("(runaway 0)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::ExcessiveLispNesting { depth: 101 }
+        );
+
+        rep(
+            "(defun depth (n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(rep("(depth 40)", &env).unwrap(), "40");
+        assert!(rep("(depth 150)", &env).is_err());
+        rep("(setq max-lisp-eval-depth 200)", &env).unwrap();
+        assert_eq!(rep("(depth 150)", &env).unwrap(), "150");
+
+        // a value that is not a fixnum falls back to the default
+        rep("(setq max-lisp-eval-depth 'foo)", &env).unwrap();
+        assert_eq!(rep("(depth 150)", &env).unwrap(), "150");
+
+        // and a tiny one is raised to the minimum, so it can be reset
+        rep("(setq max-lisp-eval-depth 0)", &env).unwrap();
+        assert_eq!(rep("(+ 1 2)", &env).unwrap(), "3");
+        let err = rep("(runaway 0)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::ExcessiveLispNesting { depth: 101 }
+        );
+        rep("(setq max-lisp-eval-depth 200)", &env).unwrap();
+        assert_eq!(rep("max-lisp-eval-depth", &env).unwrap(), "200");
+    }
+
+    #[test]
+    fn test_eval_depth_stack_limit() {
+        // a limit too high for the stack is still caught before the stack
+        // runs out, here on a test thread's default stack
+        let env = default_env();
+        rep("(defun runaway (n) (+ 1 (runaway n)))", &env).unwrap();
+        rep("(setq max-lisp-eval-depth 1000000)", &env).unwrap();
+        let err = rep("(runaway 0)", &env).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            types::RuspErr::ExcessiveLispNesting { .. }
+        ));
+        assert_eq!(rep("(+ 1 2)", &env).unwrap(), "3");
+
+        // a thread with more stack can go deeper
+        let depth = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| {
+                set_stack_size(64 * 1024 * 1024);
+                let env = default_env();
+                rep(
+                    "(defun depth (n) (if (= n 0) 0 (+ 1 (depth (- n 1)))))",
+                    &env,
+                )
+                .unwrap();
+                rep("(depth 700)", &env).unwrap()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(depth, "700");
+    }
+
+    #[test]
+    fn test_lambda_optional_rest() {
+        let env = default_env();
+        rep("(defun f (a &optional b c &rest d) (list a b c d))", &env).unwrap();
+        assert_eq!(rep("(f 1)", &env).unwrap(), "(1 nil nil nil)");
+        assert_eq!(rep("(f 1 2 3)", &env).unwrap(), "(1 2 3 nil)");
+        assert_eq!(rep("(f 1 2 3 4 5)", &env).unwrap(), "(1 2 3 (4 5))");
+        assert_eq!(
+            rep("(funcall (lambda (&rest xs) xs) 1 2)", &env).unwrap(),
+            "(1 2)"
+        );
+
+        let err = rep("(f)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongNumberOfArguments {
+                required: 1,
+                allowed: None,
+                actual: 0
+            }
+        );
+        let err = rep("((lambda (a &optional b) a) 1 2 3)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::WrongNumberOfArguments {
+                required: 1,
+                allowed: Some(2),
+                actual: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("rusp-test-load-{}.lisp", std::process::id()));
+        std::fs::write(
+            &path,
+            "#!/usr/bin/env rusp\n(defun double (x) (* x 2))\n(setq loaded (double 21))\n",
+        )
+        .unwrap();
+
+        let env = default_env();
+        let res = rep(&format!("(load \"{}\")", path.display()), &env);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.unwrap(), "t");
+        assert_eq!(rep("loaded", &env).unwrap(), "42");
+        assert_eq!(rep("(double 1)", &env).unwrap(), "2");
+        assert!(rep("(load \"/nonexistent/file.lisp\")", &env).is_err());
+    }
+
+    #[test]
+    fn test_load_large_file() {
+        // about 700 KB of nested lists; reading used to rescan the source
+        // for every list and took minutes
+        let lines = 20000;
+        let path =
+            std::env::temp_dir().join(format!("rusp-test-large-{}.lisp", std::process::id()));
+        let mut src = "(setq a (list (list 1) (list 2)))\n".repeat(lines);
+        src.push_str("  (undefined-fn)\n");
+        std::fs::write(&path, src).unwrap();
+
+        let env = default_env();
+        let started = std::time::Instant::now();
+        let err = load(path.to_str().unwrap(), &env).unwrap_err();
+        let elapsed = started.elapsed();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            format!("{}:{}:3", path.display(), lines + 1)
+        );
+        assert_eq!(rep("a", &env).unwrap(), "((1) (2))");
+        assert!(
+            elapsed < std::time::Duration::from_secs(20),
+            "{:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_rep_multiple_forms() {
+        let env = default_env();
+        assert_eq!(rep("(setq a 1) (setq b 2)", &env).unwrap(), "1\n2");
+        assert_eq!(rep("(+ a b)", &env).unwrap(), "3");
+        assert!(rep("(setq c 3) (car", &env).is_err());
+        assert!(rep("c", &env).is_err());
+    }
+
+    #[test]
+    fn test_eval_error_span() {
+        let env = default_env();
+        let err = rep("\n(progn\n  (list 1\n    (undefined-fn 2)))", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            "4:5"
+        );
+        assert_eq!(
+            err.downcast_ref::<types::RuspErr>().unwrap(),
+            &types::RuspErr::VoidFunction {
+                name: "undefined-fn".into()
+            }
+        );
+
+        // errors raised from a function body point into its definition
+        rep("(defun broken ()\n  (car 1))", &env).unwrap();
+        let err = rep("(broken)", &env).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            "2:3"
+        );
+
+        let path = std::env::temp_dir().join(format!("rusp-test-span-{}.lisp", std::process::id()));
+        std::fs::write(&path, "#!/usr/bin/env rusp\n(setq a 1)\n (undefined-fn)\n").unwrap();
+        let err = load(path.to_str().unwrap(), &env).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            format!("{}:3:2", path.display())
+        );
+    }
+
+    #[test]
+    fn test_error_message() {
+        let env = default_env();
+        for (input, expected) in [
+            (
+                "(+ 1 \"foo\")",
+                "1:1: (wrong-type-argument number-or-marker-p \"foo\")",
+            ),
+            ("(car 1)", "1:1: (wrong-type-argument listp 1)"),
+            ("(cons 1)", "1:1: (wrong-number-of-arguments (2 . 2) 1)"),
+            ("(if)", "1:1: (wrong-number-of-arguments (2 . many) 0)"),
+            ("(defvar)", "1:1: (wrong-number-of-arguments (1 . 3) 0)"),
+            (
+                "((lambda (x) x))",
+                "1:1: (wrong-number-of-arguments (1 . 1) 0)",
+            ),
+            ("undefined", "(void-variable undefined)"),
+            ("(list\n (undefined))", "2:2: (void-function undefined)"),
+            ("(throw 'nobody 1)", "1:1: (no-catch nobody 1)"),
+            ("(list 1", "1:1: (end-of-file)"),
+            ("\"a\\q\"", "1:3: (invalid-read-syntax \"\\q\")"),
+        ] {
+            let err = rep(input, &env).unwrap_err();
+            assert_eq!(format!("{:#}", err), expected, "input: {}", input);
+        }
+    }
 }
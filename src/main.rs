@@ -2,7 +2,22 @@ use anyhow::Context;
 use rusp::core;
 use rusp::types;
 
-fn repl() -> anyhow::Result<()> {
+// Leave room for `max-lisp-eval-depth` nested evaluations even in
+// unoptimized builds; deeper recursion than the stack can take signals
+// `excessive-lisp-nesting` whatever the variable says.
+const EVAL_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// Errors print as `file:line:col: (error-symbol data...)`; the full debug
+// form is only shown with `-v`.
+fn print_error(e: &anyhow::Error, verbose: bool) {
+    if verbose {
+        eprintln!("{:#?}", e);
+    } else {
+        eprintln!("{:#}", e);
+    }
+}
+
+fn repl(env: &types::RuspEnv, verbose: bool) -> anyhow::Result<()> {
     let mut rl = rustyline::Editor::<()>::new()?;
     let xdg_dirs =
         xdg::BaseDirectories::with_prefix("rusp").context("Failed to get XDG directories")?;
@@ -12,14 +27,12 @@ fn repl() -> anyhow::Result<()> {
     let history_file_path = history_file.as_path();
     _ = rl.load_history(history_file_path);
 
-    let mut env = core::default_env();
-
     loop {
         let line = rl.readline("rusp> ");
         match line {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
-                let res = core::rep(&line, &mut env);
+                let res = core::rep(&line, env);
 
                 match res {
                     Ok(res) => println!("{}", res),
@@ -27,7 +40,7 @@ fn repl() -> anyhow::Result<()> {
                         if let Some(types::RuspErr::ReplEmptyError) = e.downcast_ref() {
                             break;
                         };
-                        eprintln!("{:#?}", e);
+                        print_error(&e, verbose);
                     }
                 }
             }
@@ -54,8 +67,36 @@ fn repl() -> anyhow::Result<()> {
     Ok(())
 }
 
+// Load every file in order, then start the REPL unless a script was given.
+fn run(
+    loads: Vec<String>,
+    script: Option<String>,
+    args: Vec<String>,
+    verbose: bool,
+) -> anyhow::Result<()> {
+    let env = core::default_env();
+    env.define_variable(
+        "command-line-args",
+        args.into_iter()
+            .map(|x| types::RuspExp::Atom(types::RuspAtom::String(x)))
+            .collect(),
+    );
+
+    for file in loads.iter().chain(script.iter()) {
+        if let Err(e) = core::load(file, &env) {
+            print_error(&e, verbose);
+            std::process::exit(1);
+        }
+    }
+
+    if script.is_some() {
+        return Ok(());
+    }
+    repl(&env, verbose)
+}
+
 fn print_usage(program: &str, opts: getopts::Options) {
-    let brief = format!("Usage: {} [options]", program);
+    let brief = format!("Usage: {} [options] [FILE [ARGS...]]", program);
     print!("{}", opts.usage(&brief));
 }
 
@@ -66,6 +107,8 @@ fn main() -> anyhow::Result<()> {
     let mut opts = getopts::Options::new();
     opts.optflag("h", "help", "print this help menu");
     opts.optflag("v", "verbose", "print extra information");
+    opts.optmulti("l", "load", "load FILE before anything else", "FILE");
+    opts.parsing_style(getopts::ParsingStyle::StopAtFirstFree);
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
@@ -78,5 +121,18 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    repl()
+    let verbose = matches.opt_present("v");
+    let loads = matches.opt_strs("l");
+    let mut free = matches.free.into_iter();
+    let script = free.next();
+    let script_args = free.collect::<Vec<_>>();
+
+    std::thread::Builder::new()
+        .stack_size(EVAL_STACK_SIZE)
+        .spawn(move || {
+            core::set_stack_size(EVAL_STACK_SIZE);
+            run(loads, script, script_args, verbose)
+        })?
+        .join()
+        .unwrap_or_else(|e| std::panic::resume_unwind(e))
 }
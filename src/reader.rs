@@ -1,51 +1,222 @@
 use crate::types;
 
-static INT_PATTERN: once_cell::sync::Lazy<regex::Regex> =
-    once_cell::sync::Lazy::new(|| regex::Regex::new(r"^([+-]?[0-9]+)(?:[ ();]|$)").unwrap());
+// Decimal digits, optionally grouped by single underscores as in `1_000`.
+const DIGITS: &str = "[0-9](?:_?[0-9])*";
+const EXPONENT: &str = "[eE][+-]?[0-9]+";
+
+static INT_PATTERN: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+    regex::Regex::new(&format!(r"^([+-]?{d})(?:[\s();]|$)", d = DIGITS)).unwrap()
+});
+// `1.5`, `.5`, `1.` and `1e10`, each with an optional exponent
 static FLOAT_PATTERN: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
-    regex::Regex::new(r"^([+-]?[0-9]*\.[0-9]+)(?:[ ();]|$)").unwrap()
+    regex::Regex::new(&format!(
+        r"^([+-]?(?:{d}\.(?:{d})?(?:{e})?|\.{d}(?:{e})?|{d}{e}))(?:[\s();]|$)",
+        d = DIGITS,
+        e = EXPONENT
+    ))
+    .unwrap()
+});
+static SPECIAL_FLOAT_PATTERN: once_cell::sync::Lazy<regex::Regex> =
+    once_cell::sync::Lazy::new(|| {
+        regex::Regex::new(r"^([+-]inf\.0|[+-]nan\.0|NaN)(?:[\s();]|$)").unwrap()
+    });
+static RATIO_PATTERN: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+    regex::Regex::new(&format!(r"^([+-]?{d})/({d})(?:[\s();]|$)", d = DIGITS)).unwrap()
+});
+static RADIX_PATTERN: once_cell::sync::Lazy<regex::Regex> = once_cell::sync::Lazy::new(|| {
+    regex::Regex::new(r"^#([xXoObB])([+-]?[0-9a-zA-Z](?:_?[0-9a-zA-Z])*)(?:[\s();]|$)").unwrap()
 });
 static SYMBOL_PATTERN: once_cell::sync::Lazy<regex::Regex> =
-    once_cell::sync::Lazy::new(|| regex::Regex::new(r"^[^ ();]+").unwrap());
+    once_cell::sync::Lazy::new(|| regex::Regex::new(r"^[^\s();]+").unwrap());
+
+// The character whose code is given by exactly `len` hex digits.
+fn read_hex(chars: &mut std::str::Chars, len: usize) -> Option<char> {
+    let mut code = 0;
+    for _ in 0..len {
+        code = code * 16 + chars.next()?.to_digit(16)?;
+    }
+    char::from_u32(code)
+}
+
+// The character whose code is given by one to six hex digits in braces.
+fn read_braced_hex(chars: &mut std::str::Chars) -> Option<char> {
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut code = 0;
+    let mut len = 0;
+    loop {
+        match chars.next()? {
+            '}' if len > 0 => return char::from_u32(code),
+            c if len < 6 => code = code * 16 + c.to_digit(16)?,
+            _ => return None,
+        }
+        len += 1;
+    }
+}
 
 pub struct Reader<'a> {
+    source: &'a str,
     input: &'a str,
+    file: Option<std::sync::Arc<str>>,
+    // line bookkeeping for `span`: the position of `source[scanned]`
+    scanned: usize,
+    line: usize,
+    column: usize,
 }
 
 impl Reader<'_> {
-    pub fn new(input: &str) -> Reader {
-        Reader { input }
+    pub fn new(input: &str) -> Reader<'_> {
+        Reader {
+            source: input,
+            input,
+            file: None,
+            scanned: 0,
+            line: 1,
+            column: 1,
+        }
     }
 
-    fn skip_whitespace(&mut self) {
-        self.input = self.input.trim_start();
+    pub fn with_file<'a>(input: &'a str, file: &str) -> Reader<'a> {
+        Reader {
+            file: Some(file.into()),
+            ..Reader::new(input)
+        }
+    }
+
+    // Byte offset of `rest`, which must be a suffix of the source.
+    fn offset(&self, rest: &str) -> usize {
+        self.source.len() - rest.len()
+    }
+
+    // Scanning resumes where the previous call stopped, so spans should be
+    // taken in source order to keep reading linear; an earlier offset
+    // rescans from the start.
+    fn span(&mut self, offset: usize) -> types::RuspSpan {
+        if offset < self.scanned {
+            self.scanned = 0;
+            self.line = 1;
+            self.column = 1;
+        }
+        for c in self.source[self.scanned..offset].chars() {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.scanned = offset;
+
+        types::RuspSpan {
+            file: self.file.clone(),
+            offset,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error(&mut self, offset: usize, err: types::RuspErr) -> anyhow::Error {
+        let span = self.span(offset);
+        anyhow::Error::new(err).context(span)
+    }
+
+    // Skip whitespace along with `;` line comments, nested `#| ... |#` block
+    // comments and `#;` datum comments, which discard the next expression.
+    fn skip_whitespace(&mut self) -> anyhow::Result<()> {
+        loop {
+            let input = self.input.trim_start();
+            if input.starts_with(';') {
+                self.input = input.find('\n').map_or("", |i| &input[i + 1..]);
+            } else if input.starts_with("#|") {
+                self.input = input;
+                self.skip_block_comment()?;
+            } else if let Some(rest) = input.strip_prefix("#;") {
+                self.input = rest;
+                self.read()?;
+            } else {
+                self.input = input;
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> anyhow::Result<()> {
+        let mut depth = 0;
+        let mut rest = self.input;
+        loop {
+            if let Some(r) = rest.strip_prefix("#|") {
+                depth += 1;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("|#") {
+                depth -= 1;
+                rest = r;
+                if depth == 0 {
+                    break;
+                }
+            } else {
+                let mut chars = rest.chars();
+                if chars.next().is_none() {
+                    let start = self.offset(self.input);
+                    return Err(self.error(start, types::RuspErr::ReaderEofError));
+                }
+                rest = chars.as_str();
+            }
+        }
+        self.input = rest;
+        Ok(())
     }
 
     fn read_string(&mut self) -> anyhow::Result<types::RuspExp> {
+        let start = self.offset(self.input);
         let mut result = String::new();
         let mut chars = self.input.chars();
 
         chars.next(); // skip first "
         loop {
+            let rest = chars.as_str();
             let c = chars.next();
             match c {
                 Some('"') => {
                     break;
                 }
-                Some('\\') => match chars.next() {
-                    Some('\"') => result.push('"'),
-                    Some('\\') => result.push('\\'),
-                    Some(s) => {
-                        return Err(anyhow::anyhow!(types::RuspErr::ReaderInvalidEscapeError {
-                            char: s
-                        }))
+                Some('\\') => {
+                    let escape = match chars.next() {
+                        Some(s) => s,
+                        None => {
+                            return Err(self.error(start, types::RuspErr::ReaderUnclosedStringError))
+                        }
+                    };
+                    let c = match escape {
+                        '"' => Some('"'),
+                        '\\' => Some('\\'),
+                        'n' => Some('\n'),
+                        't' => Some('\t'),
+                        'r' => Some('\r'),
+                        '0' => Some('\0'),
+                        'e' => Some('\x1b'),
+                        'x' => read_hex(&mut chars, 2),
+                        'u' => read_hex(&mut chars, 4),
+                        'U' => read_braced_hex(&mut chars),
+                        // a backslash before a newline continues the line
+                        '\n' => continue,
+                        _ => None,
+                    };
+                    match c {
+                        Some(c) => result.push(c),
+                        None => {
+                            let offset = self.offset(rest);
+                            return Err(self.error(
+                                offset,
+                                types::RuspErr::ReaderInvalidEscapeError { char: escape },
+                            ));
+                        }
                     }
-                    None => return Err(anyhow::anyhow!(types::RuspErr::ReaderUnclosedStringError)),
-                },
+                }
                 Some(c) => {
                     result.push(c);
                 }
-                None => anyhow::bail!(types::RuspErr::ReaderUnclosedStringError),
+                None => return Err(self.error(start, types::RuspErr::ReaderUnclosedStringError)),
             }
         }
         self.input = chars.as_str();
@@ -53,22 +224,69 @@ impl Reader<'_> {
     }
 
     fn read_atom(&mut self) -> anyhow::Result<types::RuspExp> {
-        self.skip_whitespace();
+        self.skip_whitespace()?;
+
+        let start = self.offset(self.input);
 
         if let Some(m) = FLOAT_PATTERN.captures(self.input) {
             let s = m.get(1).unwrap().as_str();
-            let f = s.parse::<f64>().unwrap();
+            let f = s.replace('_', "").parse::<f64>().unwrap();
+            self.input = &self.input[s.len()..];
+
+            return Ok(types::RuspExp::Atom(types::RuspAtom::Float(f)));
+        }
+
+        if let Some(m) = SPECIAL_FLOAT_PATTERN.captures(self.input) {
+            let s = m.get(1).unwrap().as_str();
+            let f = match s {
+                "+inf.0" => f64::INFINITY,
+                "-inf.0" => f64::NEG_INFINITY,
+                _ => f64::NAN,
+            };
             self.input = &self.input[s.len()..];
 
             return Ok(types::RuspExp::Atom(types::RuspAtom::Float(f)));
         }
 
+        if let Some(m) = RATIO_PATTERN.captures(self.input) {
+            let numerator = m.get(1).unwrap().as_str().replace('_', "");
+            let numerator = dashu_int::IBig::from_str_radix(&numerator, 10).unwrap();
+            let denominator = m.get(2).unwrap().as_str().replace('_', "");
+            let denominator = dashu_int::UBig::from_str_radix(&denominator, 10).unwrap();
+            if denominator.is_zero() {
+                return Err(self.error(start, types::RuspErr::ArithError));
+            }
+            self.input = &self.input[m.get(2).unwrap().end()..];
+
+            return Ok(types::RuspExp::rational(dashu_ratio::RBig::from_parts(
+                numerator,
+                denominator,
+            )));
+        }
+
         if let Some(m) = INT_PATTERN.captures(self.input) {
             let s = m.get(1).unwrap().as_str();
-            let i = s.parse::<i64>().unwrap();
+            let i = dashu_int::IBig::from_str_radix(&s.replace('_', ""), 10).unwrap();
             self.input = &self.input[s.len()..];
 
-            return Ok(types::RuspExp::Atom(types::RuspAtom::Int(i)));
+            return Ok(types::RuspExp::integer(i));
+        }
+
+        if let Some(m) = RADIX_PATTERN.captures(self.input) {
+            let radix = match m.get(1).unwrap().as_str() {
+                "x" | "X" => 16,
+                "o" | "O" => 8,
+                _ => 2,
+            };
+            let digits = m.get(2).unwrap();
+            let i = match dashu_int::IBig::from_str_radix(&digits.as_str().replace('_', ""), radix)
+            {
+                Ok(i) => i,
+                Err(_) => return Err(self.error(start, types::RuspErr::ReaderError)),
+            };
+            self.input = &self.input[digits.end()..];
+
+            return Ok(types::RuspExp::integer(i));
         }
 
         if let Some(m) = SYMBOL_PATTERN.captures(self.input) {
@@ -80,95 +298,141 @@ impl Reader<'_> {
         unreachable!()
     }
 
-    fn read_cons(&mut self) -> anyhow::Result<types::RuspExp> {
-        self.skip_whitespace();
-
-        anyhow::ensure!(!self.input.is_empty(), types::RuspErr::ReaderEofError);
-
-        if self.input.starts_with(')') {
-            self.input = &self.input[1..]; // skip ')'
-            return Ok(types::nil!());
+    // Whether the input starts with the `.` of a dotted pair rather than
+    // with an atom such as `.5` or `.b`.
+    fn at_dot(&self) -> bool {
+        let mut chars = self.input.chars();
+        if chars.next() != Some('.') {
+            return false;
         }
+        match chars.next() {
+            Some(c) => c.is_whitespace() || "();".contains(c),
+            None => true,
+        }
+    }
 
-        let car = self.read()?;
-
-        self.skip_whitespace();
-        if self.input.starts_with('.') {
-            self.input = &self.input[1..]; // skip '.'
+    // `open` is the offset of the list's `(`, which an unterminated list is
+    // reported at. The elements are collected first and linked up from the
+    // tail, so a long list does not nest a call per element.
+    fn read_cons(&mut self, open: usize) -> anyhow::Result<types::RuspExp> {
+        let mut elements = Vec::new();
+        let mut tail = types::nil!();
+        loop {
+            self.skip_whitespace()?;
 
-            self.skip_whitespace();
+            if self.input.is_empty() {
+                return Err(self.error(open, types::RuspErr::ReaderEofError));
+            }
 
             if self.input.starts_with(')') {
                 self.input = &self.input[1..]; // skip ')'
-                anyhow::bail!(types::RuspErr::ReaderEofError);
+                break;
             }
 
-            let cdr = self.read()?;
+            if !elements.is_empty() && self.at_dot() {
+                let dot = self.offset(self.input);
+                self.input = &self.input[1..]; // skip '.'
+
+                self.skip_whitespace()?;
+
+                if self.input.starts_with(')') {
+                    self.input = &self.input[1..]; // skip ')'
+                    return Err(self.error(dot, types::RuspErr::ReaderEofError));
+                }
+
+                tail = self.read()?;
 
-            self.skip_whitespace();
-            anyhow::ensure!(self.input.starts_with(')'), types::RuspErr::ReaderEofError);
+                self.skip_whitespace()?;
+                if !self.input.starts_with(')') {
+                    let offset = self.offset(self.input);
+                    return Err(self.error(offset, types::RuspErr::ReaderEofError));
+                }
 
-            self.input = &self.input[1..]; // skip ')'
+                self.input = &self.input[1..]; // skip ')'
+                break;
+            }
 
-            return Ok(types::RuspExp::Cons {
-                car: Box::new(car),
-                cdr: Box::new(cdr),
-            });
+            elements.push(self.read()?);
         }
 
-        let cdr = self.read_cons()?;
+        Ok(elements
+            .into_iter()
+            .rev()
+            .fold(tail, |cdr, car| types::RuspExp::Cons {
+                car: std::sync::Arc::new(car),
+                cdr: std::sync::Arc::new(cdr),
+            }))
+    }
+
+    // Read the form after a `len` byte prefix such as `'` and wrap it as
+    // `(symbol form)`.
+    fn read_prefixed(&mut self, len: usize, symbol: &str) -> anyhow::Result<types::RuspExp> {
+        self.input = &self.input[len..]; // skip prefix
         Ok(types::RuspExp::Cons {
-            car: Box::new(car),
-            cdr: Box::new(cdr),
+            car: std::sync::Arc::new(types::RuspExp::Atom(types::RuspAtom::Symbol(
+                symbol.to_string(),
+            ))),
+            cdr: std::sync::Arc::new(types::RuspExp::Cons {
+                car: std::sync::Arc::new(self.read()?),
+                cdr: std::sync::Arc::new(types::nil!()),
+            }),
         })
     }
 
     pub fn read(&mut self) -> anyhow::Result<types::RuspExp> {
-        self.skip_whitespace();
-        let c = self
-            .input
-            .chars()
-            .next()
-            .ok_or(types::RuspErr::ReaderEofError)?;
-
-        match c {
-            '\'' => {
-                self.input = &self.input[1..]; // skip '\''
-                Ok(types::RuspExp::Cons {
-                    car: Box::new(types::RuspExp::Atom(types::RuspAtom::Symbol(
-                        "quote".to_string(),
-                    ))),
-                    cdr: Box::new(types::RuspExp::Cons {
-                        car: Box::new(self.read()?),
-                        cdr: Box::new(types::nil!()),
-                    }),
-                })
-            }
+        self.skip_whitespace()?;
+        let start = self.offset(self.input);
+        // taken before the elements are read, which lie further on
+        let span = self.span(start);
+        let c = match self.input.chars().next() {
+            Some(c) => c,
+            None => return Err(self.error(start, types::RuspErr::ReaderEofError)),
+        };
+
+        let exp = match c {
+            '\'' => self.read_prefixed(1, "quote")?,
+            '`' => self.read_prefixed(1, "backquote")?,
+            ',' if self.input.starts_with(",@") => self.read_prefixed(2, "unquote-splicing")?,
+            ',' => self.read_prefixed(1, "unquote")?,
             ':' => {
                 self.input = &self.input[1..]; // skip ':'
 
                 if let Some(m) = SYMBOL_PATTERN.captures(self.input) {
                     let s = m.get(0).unwrap().as_str();
                     self.input = &self.input[s.len()..];
-                    return Ok(types::RuspExp::Atom(types::RuspAtom::Keyword(
-                        s.to_string(),
-                    )));
+                    types::RuspExp::Atom(types::RuspAtom::Keyword(s.to_string()))
+                } else {
+                    types::RuspExp::Atom(types::RuspAtom::Keyword("".to_string()))
                 }
-
-                Ok(types::RuspExp::Atom(types::RuspAtom::Keyword(
-                    "".to_string(),
-                )))
             }
-            '\"' => self.read_string(),
+            '\"' => self.read_string()?,
             '(' => {
                 self.input = &self.input[1..]; // skip '('
-                self.read_cons()
+                self.read_cons(start)?
             }
             ')' => {
                 self.input = &self.input[1..]; // skip ')'
-                Err(anyhow::anyhow!(types::RuspErr::ReaderError))
+                return Err(self.error(start, types::RuspErr::ReaderError));
+            }
+            _ => self.read_atom()?,
+        };
+
+        if exp.consp() {
+            exp.set_span(span);
+        }
+        Ok(exp)
+    }
+
+    // Read top-level forms until the input runs out; anything left over that
+    // does not form a complete expression is an error.
+    pub fn read_all(&mut self) -> anyhow::Result<Vec<types::RuspExp>> {
+        let mut forms = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            if self.input.is_empty() {
+                return Ok(forms);
             }
-            _ => self.read_atom(),
+            forms.push(self.read()?);
         }
     }
 }
@@ -176,6 +440,7 @@ impl Reader<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
     use types::RuspAtom::*;
     use types::RuspErr;
     use types::RuspExp::*;
@@ -185,12 +450,18 @@ mod tests {
         let input = "";
         let mut reader = Reader::new(input);
         let exp = reader.read().unwrap_err();
-        assert_eq!(exp.to_string(), RuspErr::ReaderEofError.to_string());
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
 
         let input = "   ";
         let mut reader = Reader::new(input);
         let exp = reader.read().unwrap_err();
-        assert_eq!(exp.to_string(), RuspErr::ReaderEofError.to_string());
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
 
         let input = "42";
         let mut reader = Reader::new(input);
@@ -236,12 +507,12 @@ mod tests {
         assert_eq!(
             exp,
             Cons {
-                car: Box::new(Atom(Int(1))),
-                cdr: Box::new(Cons {
-                    car: Box::new(Atom(Int(2))),
-                    cdr: Box::new(Cons {
-                        car: Box::new(Atom(Int(3))),
-                        cdr: Box::new(types::nil!()),
+                car: Arc::new(Atom(Int(1))),
+                cdr: Arc::new(Cons {
+                    car: Arc::new(Atom(Int(2))),
+                    cdr: Arc::new(Cons {
+                        car: Arc::new(Atom(Int(3))),
+                        cdr: Arc::new(types::nil!()),
                     }),
                 }),
             }
@@ -253,17 +524,435 @@ mod tests {
         assert_eq!(
             exp,
             Cons {
-                car: Box::new(Atom(Int(1))),
-                cdr: Box::new(Cons {
-                    car: Box::new(Atom(Int(2))),
-                    cdr: Box::new(Atom(Int(3))),
+                car: Arc::new(Atom(Int(1))),
+                cdr: Arc::new(Cons {
+                    car: Arc::new(Atom(Int(2))),
+                    cdr: Arc::new(Atom(Int(3))),
                 }),
             }
         );
 
+        let input = "(+ .5 1 .5e1 .b) (1 .(2)) (1 .;comment\n 2)";
+        let mut reader = Reader::new(input);
+        let exps = reader.read_all().unwrap();
+        assert_eq!(exps[0].to_string(), "(+ 0.5 1 5.0 .b)");
+        assert_eq!(exps[1].to_string(), "(1 2)");
+        assert_eq!(exps[2].to_string(), "(1 . 2)");
+
         let input = "(1 2 . 3";
         let mut reader = Reader::new(input);
         let exp = reader.read().unwrap_err();
-        assert_eq!(exp.to_string(), RuspErr::ReaderEofError.to_string());
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
+    }
+
+    #[test]
+    fn test_read_long_list() {
+        // reading must not recurse once per element, even on a test thread's
+        // small stack
+        let elements = (0..200000).map(|i| i.to_string()).collect::<Vec<_>>();
+        let input = format!("({}) ({} . end)", elements.join(" "), elements.join(" "));
+        let mut reader = Reader::new(&input);
+        let exps = reader.read_all().unwrap();
+        assert_eq!(exps[0].into_iter().count(), 200000);
+        assert!(exps[0].into_iter().all(|x| x.is_ok()));
+        assert!(exps[1].to_string().ends_with(" 199998 199999 . end)"));
+    }
+
+    #[test]
+    fn test_read_backquote() {
+        let wrap = |symbol: &str, exp| Cons {
+            car: Arc::new(Atom(Symbol(symbol.to_string()))),
+            cdr: Arc::new(Cons {
+                car: Arc::new(exp),
+                cdr: Arc::new(Atom(Symbol("nil".to_string()))),
+            }),
+        };
+
+        let input = "`(a ,b ,@c)";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read().unwrap(),
+            wrap(
+                "backquote",
+                Cons {
+                    car: Arc::new(Atom(Symbol("a".to_string()))),
+                    cdr: Arc::new(Cons {
+                        car: Arc::new(wrap("unquote", Atom(Symbol("b".to_string())))),
+                        cdr: Arc::new(Cons {
+                            car: Arc::new(wrap("unquote-splicing", Atom(Symbol("c".to_string())))),
+                            cdr: Arc::new(Atom(Symbol("nil".to_string()))),
+                        }),
+                    }),
+                }
+            )
+        );
+
+        // every short form prints back the way it was read
+        for input in [
+            "'a",
+            "`(a ,b ,@c)",
+            "`(a `(b ,(c ,d)))",
+            "(a . ,b)",
+            "(quote a b)",
+        ] {
+            let mut reader = Reader::new(input);
+            assert_eq!(reader.read().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_read_bignum() {
+        let input = "9223372036854775807 9223372036854775808 -9223372036854775809";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(Int(i64::MAX)),
+                Atom(BigInt(dashu_int::IBig::from(i64::MAX) + 1)),
+                Atom(BigInt(dashu_int::IBig::from(i64::MIN) - 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_string_escapes() {
+        let input = r#""a\"b\\c" "\n\t\r\0\e" "\x41\x7f" "éあ" "\U{1F600}\U{41}" "one \
+two""#;
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(String("a\"b\\c".to_string())),
+                Atom(String("\n\t\r\0\x1b".to_string())),
+                Atom(String("A\x7f".to_string())),
+                Atom(String("\u{e9}\u{3042}".to_string())),
+                Atom(String("\u{1F600}A".to_string())),
+                Atom(String("one two".to_string())),
+            ]
+        );
+
+        for (input, escape) in [
+            (r#""ab\q""#, 'q'),
+            (r#""ab\x4""#, 'x'),
+            (r#""ab\xg1""#, 'x'),
+            (r#""ab\u12""#, 'u'),
+            (r#""ab\ud800""#, 'u'),
+            (r#""ab\U{}""#, 'U'),
+            (r#""ab\U41""#, 'U'),
+            (r#""ab\U{1234567}""#, 'U'),
+            (r#""ab\U{110000}""#, 'U'),
+        ] {
+            let mut reader = Reader::new(input);
+            let err = reader.read_all().unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+                "1:4",
+                "input: {}",
+                input
+            );
+            assert_eq!(
+                err.downcast::<RuspErr>().unwrap(),
+                RuspErr::ReaderInvalidEscapeError { char: escape },
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        for s in [
+            "plain",
+            "say \"hi\"",
+            "back\\slash",
+            "line\nbreak\ttab\r",
+            "\0\x1b\x07\u{7f}\u{85}",
+            "\u{e9}\u{3042}\u{1F600}",
+            "",
+        ] {
+            let printed = Atom(String(s.to_string())).to_string();
+            let mut reader = Reader::new(&printed);
+            assert_eq!(
+                reader.read().unwrap(),
+                Atom(String(s.to_string())),
+                "{}",
+                printed
+            );
+        }
+        assert_eq!(
+            Atom(String("a\"b\n".to_string())).to_string(),
+            r#""a\"b\n""#
+        );
+    }
+
+    #[test]
+    fn test_read_number_syntax() {
+        let input = "1e10 -1.5E-3 1. +2.e2 .5e1 1_000_000 1_000.000_1 #x1F #X-ff #b1010 #o17 #x1_0";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(Float(1e10)),
+                Atom(Float(-1.5e-3)),
+                Atom(Float(1.0)),
+                Atom(Float(200.0)),
+                Atom(Float(5.0)),
+                Atom(Int(1_000_000)),
+                Atom(Float(1000.0001)),
+                Atom(Int(31)),
+                Atom(Int(-255)),
+                Atom(Int(10)),
+                Atom(Int(15)),
+                Atom(Int(16)),
+            ]
+        );
+
+        let input = "#x10000000000000000 1_000/3_000";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(BigInt(dashu_int::IBig::from(1) << 64)),
+                Atom(Ratio(dashu_ratio::RBig::from_parts(1.into(), 3u8.into()))),
+            ]
+        );
+
+        let input = "+inf.0 -inf.0 +nan.0 NaN";
+        let mut reader = Reader::new(input);
+        let exps = reader.read_all().unwrap();
+        assert_eq!(exps[0], Atom(Float(f64::INFINITY)));
+        assert_eq!(exps[1], Atom(Float(f64::NEG_INFINITY)));
+        assert!(matches!(exps[2], Atom(Float(f)) if f.is_nan()));
+        assert!(matches!(exps[3], Atom(Float(f)) if f.is_nan()));
+
+        // near misses stay symbols
+        let input = "1_ 1__0 _1 e10 1e inf nan 1.2.3";
+        let mut reader = Reader::new(input);
+        assert!(reader.read_all().unwrap().iter().all(|x| x.symbolp()));
+
+        let mut reader = Reader::new("(a #b102)");
+        let err = reader.read_all().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            "1:4"
+        );
+        assert_eq!(err.downcast::<RuspErr>().unwrap(), RuspErr::ReaderError);
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        for f in [
+            2.0,
+            -0.5,
+            0.1,
+            1e21,
+            1e-7,
+            123456789.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ] {
+            let printed = Atom(Float(f)).to_string();
+            let mut reader = Reader::new(&printed);
+            assert_eq!(reader.read().unwrap(), Atom(Float(f)), "{}", printed);
+        }
+        let printed = Atom(Float(f64::NAN)).to_string();
+        let mut reader = Reader::new(&printed);
+        assert!(matches!(reader.read().unwrap(), Atom(Float(f)) if f.is_nan()));
+    }
+
+    #[test]
+    fn test_read_ratio() {
+        let input = "1/3 -2/4 4/2 +0/5 1/ 1/a";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(Ratio(dashu_ratio::RBig::from_parts(1.into(), 3u8.into()))),
+                Atom(Ratio(dashu_ratio::RBig::from_parts(
+                    (-1).into(),
+                    2u8.into()
+                ))),
+                Atom(Int(2)),
+                Atom(Int(0)),
+                Atom(Symbol("1/".to_string())),
+                Atom(Symbol("1/a".to_string())),
+            ]
+        );
+
+        let mut reader = Reader::new("(a 1/0)");
+        let err = reader.read_all().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<types::RuspSpan>().unwrap().to_string(),
+            "1:4"
+        );
+        assert_eq!(err.downcast::<RuspErr>().unwrap(), RuspErr::ArithError);
+    }
+
+    #[test]
+    fn test_read_all() {
+        let input = "";
+        let mut reader = Reader::new(input);
+        assert_eq!(reader.read_all().unwrap(), vec![]);
+
+        let input = " 1 (2 3)\n:a ";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(Int(1)),
+                Cons {
+                    car: Arc::new(Atom(Int(2))),
+                    cdr: Arc::new(Cons {
+                        car: Arc::new(Atom(Int(3))),
+                        cdr: Arc::new(Atom(Symbol("nil".to_string()))),
+                    }),
+                },
+                Atom(Keyword("a".to_string())),
+            ]
+        );
+
+        let input = "(a b))";
+        let mut reader = Reader::new(input);
+        let exp = reader.read_all().unwrap_err();
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderError.to_string()
+        );
+
+        let input = "(a b) (c";
+        let mut reader = Reader::new(input);
+        let exp = reader.read_all().unwrap_err();
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
+    }
+
+    #[test]
+    fn test_read_comments() {
+        let input = "; comment\n1 ; trailing\n2";
+        let mut reader = Reader::new(input);
+        assert_eq!(reader.read_all().unwrap(), vec![Atom(Int(1)), Atom(Int(2))]);
+
+        let input = "(a ; comment\n b)";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read().unwrap(),
+            Cons {
+                car: Arc::new(Atom(Symbol("a".to_string()))),
+                cdr: Arc::new(Cons {
+                    car: Arc::new(Atom(Symbol("b".to_string()))),
+                    cdr: Arc::new(Atom(Symbol("nil".to_string()))),
+                }),
+            }
+        );
+
+        let input = "#| block #| nested |# still comment |# 1 #|x|#";
+        let mut reader = Reader::new(input);
+        assert_eq!(reader.read_all().unwrap(), vec![Atom(Int(1))]);
+
+        let input = "#| unclosed #| nested |#";
+        let mut reader = Reader::new(input);
+        let exp = reader.read_all().unwrap_err();
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
+
+        let input = "#;(ignored (list)) 1 #; 2 3";
+        let mut reader = Reader::new(input);
+        assert_eq!(reader.read_all().unwrap(), vec![Atom(Int(1)), Atom(Int(3))]);
+
+        let input = "(1 #;2)";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read().unwrap(),
+            Cons {
+                car: Arc::new(Atom(Int(1))),
+                cdr: Arc::new(Atom(Symbol("nil".to_string()))),
+            }
+        );
+
+        let input = "#;";
+        let mut reader = Reader::new(input);
+        let exp = reader.read_all().unwrap_err();
+        assert_eq!(
+            exp.root_cause().to_string(),
+            RuspErr::ReaderEofError.to_string()
+        );
+
+        let input = "foo\nbar";
+        let mut reader = Reader::new(input);
+        assert_eq!(
+            reader.read_all().unwrap(),
+            vec![
+                Atom(Symbol("foo".to_string())),
+                Atom(Symbol("bar".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_span() {
+        let input = "(a\n  (b \"c\")\n)";
+        let mut reader = Reader::with_file(input, "test.lisp");
+        let exp = reader.read().unwrap();
+        let span = exp.span().unwrap();
+        assert_eq!((span.offset, span.line, span.column), (0, 1, 1));
+        assert_eq!(span.to_string(), "test.lisp:1:1");
+
+        let inner = exp.cdr().unwrap().car().unwrap();
+        assert_eq!(inner.span().unwrap().to_string(), "test.lisp:2:3");
+        // a copy shares the original's span
+        assert_eq!(inner.clone().span(), inner.span());
+
+        let input = "1 'x";
+        let mut reader = Reader::new(input);
+        let exps = reader.read_all().unwrap();
+        assert_eq!(exps[0].span(), None);
+        assert_eq!(exps[1].span().unwrap().to_string(), "1:3");
+    }
+
+    #[test]
+    fn test_read_error_span() {
+        fn error_span(input: &str) -> (RuspErr, std::string::String) {
+            let mut reader = Reader::new(input);
+            let err = reader.read_all().unwrap_err();
+            let span = err.downcast_ref::<types::RuspSpan>().unwrap().to_string();
+            (err.downcast::<RuspErr>().unwrap(), span)
+        }
+
+        assert_eq!(
+            error_span("1\n (a\n b"),
+            (RuspErr::ReaderEofError, "2:2".to_string())
+        );
+        assert_eq!(
+            error_span("(a)\n  )"),
+            (RuspErr::ReaderError, "2:3".to_string())
+        );
+        assert_eq!(
+            error_span("(\"\u{e9}\" \"abc"),
+            (RuspErr::ReaderUnclosedStringError, "1:6".to_string())
+        );
+        assert_eq!(
+            error_span("\"ab\\q\""),
+            (
+                RuspErr::ReaderInvalidEscapeError { char: 'q' },
+                "1:4".to_string()
+            )
+        );
+        assert_eq!(
+            error_span("(a . b c)"),
+            (RuspErr::ReaderEofError, "1:8".to_string())
+        );
+        assert_eq!(
+            error_span("x #| y"),
+            (RuspErr::ReaderEofError, "1:3".to_string())
+        );
     }
 }
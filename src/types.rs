@@ -1,76 +1,349 @@
-#[derive(thiserror::Error, Debug, Eq, PartialEq)]
+#[derive(thiserror::Error, Debug, PartialEq)]
 pub enum RuspErr {
-    #[error("DummyError")]
+    #[error("(dummy-error)")]
     DummyError,
 
-    #[error("ReplEmptyError")]
+    #[error("(empty-input)")]
     ReplEmptyError,
-    #[error("ReaderError")]
+    #[error("(invalid-read-syntax)")]
     ReaderError,
-    #[error("ReaderEofError")]
+    #[error("(end-of-file)")]
     ReaderEofError,
-    #[error("ReaderInvalidEscapeError")]
+    #[error("(invalid-read-syntax \"\\{char}\")")]
     ReaderInvalidEscapeError { char: char },
-    #[error("ReaderUnclosedStringError")]
+    #[error("(end-of-file \"unterminated string\")")]
     ReaderUnclosedStringError,
 
-    #[error("WrongTypeArgument")]
+    #[error("(wrong-type-argument {expected} {actual})")]
     WrongTypeArgument {
         expected: std::borrow::Cow<'static, str>,
-        actual: std::borrow::Cow<'static, str>,
+        actual: RuspExp,
     },
-    #[error("WrongNumberOfArguments")]
+    #[error(
+        "(wrong-number-of-arguments ({required} . {}) {actual})",
+        .allowed.map_or("many".to_string(), |x| x.to_string())
+    )]
     WrongNumberOfArguments {
         required: usize,
         allowed: Option<usize>,
         actual: usize,
     },
-    #[error("VoidVariable")]
+    #[error("(void-variable {name})")]
     VoidVariable {
         name: std::borrow::Cow<'static, str>,
     },
-    #[error("VoidFunction")]
+    #[error("(void-function {name})")]
     VoidFunction {
         name: std::borrow::Cow<'static, str>,
     },
+    #[error("(excessive-lisp-nesting {depth})")]
+    ExcessiveLispNesting { depth: usize },
+    #[error("(arith-error)")]
+    ArithError,
+    #[error("(overflow-error)")]
+    OverflowError,
+}
+
+impl RuspErr {
+    // The error symbol that `condition-case` handlers match against.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            RuspErr::DummyError => "error",
+            RuspErr::ReplEmptyError
+            | RuspErr::ReaderEofError
+            | RuspErr::ReaderUnclosedStringError => "end-of-file",
+            RuspErr::ReaderError | RuspErr::ReaderInvalidEscapeError { .. } => {
+                "invalid-read-syntax"
+            }
+            RuspErr::WrongTypeArgument { .. } => "wrong-type-argument",
+            RuspErr::WrongNumberOfArguments { .. } => "wrong-number-of-arguments",
+            RuspErr::VoidVariable { .. } => "void-variable",
+            RuspErr::VoidFunction { .. } => "void-function",
+            RuspErr::ExcessiveLispNesting { .. } => "excessive-lisp-nesting",
+            RuspErr::ArithError => "arith-error",
+            RuspErr::OverflowError => "overflow-error",
+        }
+    }
+}
+
+// Every error symbol eventually leads up to `error`; symbols that are not
+// listed here are direct children of it.
+fn error_parent(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        "error" => None,
+        "excessive-lisp-nesting" => Some("recursion-error"),
+        "overflow-error" => Some("range-error"),
+        "range-error" => Some("arith-error"),
+        _ => Some("error"),
+    }
+}
+
+// The conditions an error symbol belongs to, starting with the symbol itself.
+pub fn error_conditions(symbol: &str) -> Vec<String> {
+    let mut conditions = vec![symbol.to_string()];
+    let mut current = symbol;
+    while let Some(parent) = error_parent(current) {
+        conditions.push(parent.to_string());
+        current = parent;
+    }
+    conditions
 }
 
 #[derive(Clone)]
 pub enum RuspAtom {
     Int(i64),
+    // Only holds integers outside the `i64` range; see `RuspExp::integer`.
+    BigInt(dashu_int::IBig),
+    // Only holds non-integral rationals; see `RuspExp::rational`.
+    Ratio(dashu_ratio::RBig),
     Float(f64),
     String(String),
     Symbol(String),
     Keyword(String),
-    Func(fn(&RuspExp, &mut RuspEnv) -> anyhow::Result<RuspExp>),
+    Func(fn(&RuspExp, &RuspEnv) -> anyhow::Result<RuspExp>),
+    Special(fn(&RuspExp, &RuspEnv) -> anyhow::Result<RuspTail>),
     Lambda {
-        params: Box<RuspExp>,
-        body: Box<RuspExp>,
+        params: std::sync::Arc<RuspExp>,
+        body: std::sync::Arc<RuspExp>,
+        env: RuspEnv,
     },
+    // A macro holds the lambda that expands a call from its unevaluated
+    // arguments.
+    Macro(std::sync::Arc<RuspExp>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum RuspExp {
     Atom(RuspAtom),
     Cons {
-        car: Box<RuspExp>,
-        cdr: Box<RuspExp>,
+        car: std::sync::Arc<RuspExp>,
+        cdr: std::sync::Arc<RuspExp>,
     },
 }
 
-#[derive(Debug, PartialEq, Default, Clone)]
-pub struct RuspEnv<'a> {
+// Stands in for the cdr of a cell that is being dropped.
+static DROPPED_CDR: once_cell::sync::Lazy<std::sync::Arc<RuspExp>> =
+    once_cell::sync::Lazy::new(|| std::sync::Arc::new(nil!()));
+
+// Lists are dropped iteratively along their cdr chain; the derived drop
+// would recurse once per cell and overflow the stack on long lists.
+impl Drop for RuspExp {
+    fn drop(&mut self) {
+        let mut cdr = match self {
+            RuspExp::Cons { cdr, .. } if cdr.consp() => std::mem::replace(cdr, DROPPED_CDR.clone()),
+            _ => return,
+        };
+        // unlink the rest of each cell this was the last owner of, so that
+        // dropping the cell itself does not recurse
+        while let Ok(mut cell) = std::sync::Arc::try_unwrap(cdr) {
+            cdr = match &mut cell {
+                RuspExp::Cons { cdr, .. } if cdr.consp() => {
+                    std::mem::replace(cdr, DROPPED_CDR.clone())
+                }
+                _ => return,
+            };
+        }
+    }
+}
+
+// What a special form hands back to `core::eval`: either a finished value,
+// or a form left in tail position for the evaluator loop to continue with.
+#[derive(Debug)]
+pub enum RuspTail {
+    Value(RuspExp),
+    Eval(RuspExp, RuspEnv),
+}
+
+#[derive(Debug, Default)]
+pub struct RuspFrame {
     pub variable: std::collections::HashMap<String, RuspExp>,
     pub function: std::collections::HashMap<String, RuspExp>,
-    pub outer: Option<&'a RuspEnv<'a>>,
+    pub outer: Option<RuspEnv>,
+}
+
+// Frames live on the heap and are shared, so a lambda can keep its
+// defining environment alive after the frame that created it returns.
+// They are `Send + Sync` so that Lisp values can travel inside errors.
+// Frames that only keep each other alive are freed by `collect_cycles`.
+#[derive(Debug, Clone)]
+pub struct RuspEnv(std::sync::Arc<std::sync::Mutex<RuspFrame>>);
+
+// The frames created on this thread, for `collect_cycles` to look through.
+// A lambda stored in the frame it closes over, or in one further in, makes
+// a cycle that reference counting never frees.
+struct FrameTable {
+    frames: Vec<std::sync::Weak<std::sync::Mutex<RuspFrame>>>,
+    // objects the last collection found to be live
+    live: usize,
+}
+
+thread_local! {
+    static FRAMES: std::cell::RefCell<FrameTable> = const {
+        std::cell::RefCell::new(FrameTable {
+            frames: Vec::new(),
+            live: 0,
+        })
+    };
+}
+
+// The references among frames and the cells and code reachable from them,
+// keyed by address.
+#[derive(Default)]
+struct FrameGraph {
+    strong: std::collections::HashMap<usize, usize>,
+    internal: std::collections::HashMap<usize, usize>,
+    edges: std::collections::HashMap<usize, Vec<usize>>,
+    pending: Vec<std::sync::Arc<RuspExp>>,
+}
+
+impl FrameGraph {
+    fn edge(&mut self, from: usize, to: usize) {
+        *self.internal.entry(to).or_default() += 1;
+        self.edges.entry(from).or_default().push(to);
+    }
+
+    fn node(&mut self, from: usize, node: &std::sync::Arc<RuspExp>) {
+        let id = std::sync::Arc::as_ptr(node) as usize;
+        self.edge(from, id);
+        if let std::collections::hash_map::Entry::Vacant(entry) = self.strong.entry(id) {
+            // counted before the copy below adds to it
+            entry.insert(std::sync::Arc::strong_count(node));
+            self.pending.push(node.clone());
+        }
+    }
+
+    fn value(&mut self, from: usize, x: &RuspExp) {
+        match x {
+            RuspExp::Cons { car, cdr } => {
+                self.node(from, car);
+                self.node(from, cdr);
+            }
+            RuspExp::Atom(RuspAtom::Lambda { params, body, env }) => {
+                self.node(from, params);
+                self.node(from, body);
+                self.edge(from, env.id());
+            }
+            RuspExp::Atom(RuspAtom::Macro(expander)) => self.node(from, expander),
+            _ => (),
+        }
+    }
+}
+
+// Free the frames on this thread that are only kept alive by cycles.
+//
+// Every reference held by a frame, or by a cell or lambda reachable from
+// one, is counted. An object with more references than that is also held
+// from elsewhere, such as an evaluation in progress, so it is live along
+// with everything it reaches. The remaining frames are garbage; emptying
+// them breaks their cycles so that they drop.
+pub fn collect_cycles() {
+    let frames = FRAMES.with(|x| {
+        let mut table = x.borrow_mut();
+        table.frames.retain(|frame| frame.strong_count() > 0);
+        table
+            .frames
+            .iter()
+            .filter_map(|frame| frame.upgrade())
+            .collect::<Vec<_>>()
+    });
+
+    let mut graph = FrameGraph::default();
+    let mut roots = vec![];
+    for frame in &frames {
+        let id = std::sync::Arc::as_ptr(frame) as usize;
+        // less the copy in `frames`
+        graph
+            .strong
+            .insert(id, std::sync::Arc::strong_count(frame) - 1);
+        // a frame that is locked is in use
+        let frame = match frame.try_lock() {
+            Ok(frame) => frame,
+            Err(_) => {
+                roots.push(id);
+                continue;
+            }
+        };
+        for x in frame.variable.values().chain(frame.function.values()) {
+            graph.value(id, x);
+        }
+        if let Some(outer) = &frame.outer {
+            graph.edge(id, outer.id());
+        }
+    }
+    while let Some(node) = graph.pending.pop() {
+        graph.value(std::sync::Arc::as_ptr(&node) as usize, &node);
+    }
+
+    roots.extend(
+        graph
+            .strong
+            .iter()
+            .filter(|(id, strong)| **strong > graph.internal.get(id).copied().unwrap_or(0))
+            .map(|(id, _)| *id),
+    );
+    let mut live = std::collections::HashSet::new();
+    while let Some(id) = roots.pop() {
+        if live.insert(id) {
+            roots.extend(graph.edges.get(&id).into_iter().flatten());
+        }
+    }
+
+    FRAMES.with(|x| x.borrow_mut().live = live.len());
+    for frame in &frames {
+        if live.contains(&(std::sync::Arc::as_ptr(frame) as usize)) {
+            continue;
+        }
+        // dropped once the lock is released, as they may hold this frame
+        let garbage = std::mem::take(&mut *frame.lock().unwrap());
+        drop(garbage);
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("(no-catch {tag} {value})")]
+pub struct RuspThrow {
+    pub tag: RuspExp,
+    pub value: RuspExp,
+}
+
+// An error raised from Lisp by `signal`, `error` and friends. It prints as
+// the `(symbol . data)` object that `condition-case` hands to its handler.
+#[derive(Debug)]
+pub struct RuspSignal {
+    pub symbol: String,
+    pub data: RuspExp,
+}
+
+impl std::error::Error for RuspSignal {}
+
+// A position in source text. `line` and `column` count from 1, and the
+// column counts characters rather than bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuspSpan {
+    pub file: Option<std::sync::Arc<str>>,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
+// Spans of cons cells built by the reader, keyed by the address of their
+// car. Holding a weak reference keeps that address from being reused by an
+// unrelated cell while the entry is in the table.
+#[derive(Default)]
+struct SpanTable {
+    spans: std::collections::HashMap<usize, (std::sync::Weak<RuspExp>, RuspSpan)>,
+    live: usize,
+}
+
+static SPANS: once_cell::sync::Lazy<std::sync::Mutex<SpanTable>> =
+    once_cell::sync::Lazy::new(Default::default);
+
 macro_rules! rusp_func {
     ($env: ident, $(($key:expr, $value:path)),*,) => {
         {
             $(
-                $env.function.insert(
-                    $key.to_string(),
+                $env.define_function(
+                    $key,
                     crate::types::RuspExp::Atom(crate::types::RuspAtom::Func($value)),
                 );
             )*
@@ -79,6 +352,17 @@ macro_rules! rusp_func {
     };
 }
 
+macro_rules! rusp_special {
+    ($env: ident, $(($key:expr, $value:path)),*,) => {
+        $(
+            $env.define_function(
+                $key,
+                crate::types::RuspExp::Atom(crate::types::RuspAtom::Special($value)),
+            );
+        )*
+    };
+}
+
 macro_rules! nil {
     () => {
         crate::types::RuspExp::Atom(crate::types::RuspAtom::Symbol("nil".to_string()))
@@ -93,17 +377,36 @@ macro_rules! t {
 
 pub(crate) use nil;
 pub(crate) use rusp_func;
+pub(crate) use rusp_special;
 pub(crate) use t;
 
 impl PartialEq for RuspAtom {
     fn eq(&self, other: &RuspAtom) -> bool {
-        if let RuspAtom::Func(_) = self {
-            return false;
-        }
-        if let RuspAtom::Func(_) = other {
-            return false;
+        match (self, other) {
+            (RuspAtom::Int(a), RuspAtom::Int(b)) => a == b,
+            (RuspAtom::BigInt(a), RuspAtom::BigInt(b)) => a == b,
+            (RuspAtom::Ratio(a), RuspAtom::Ratio(b)) => a == b,
+            (RuspAtom::Float(a), RuspAtom::Float(b)) => a == b,
+            (RuspAtom::String(a), RuspAtom::String(b)) => a == b,
+            (RuspAtom::Symbol(a), RuspAtom::Symbol(b)) => a == b,
+            (RuspAtom::Keyword(a), RuspAtom::Keyword(b)) => a == b,
+            (
+                RuspAtom::Lambda { params, body, env },
+                RuspAtom::Lambda {
+                    params: other_params,
+                    body: other_body,
+                    env: other_env,
+                },
+            ) => params == other_params && body == other_body && env == other_env,
+            (RuspAtom::Macro(a), RuspAtom::Macro(b)) => a == b,
+            _ => false,
         }
-        self == other
+    }
+}
+
+impl PartialEq for RuspEnv {
+    fn eq(&self, other: &RuspEnv) -> bool {
+        std::sync::Arc::ptr_eq(&self.0, &other.0)
     }
 }
 
@@ -113,32 +416,104 @@ impl std::fmt::Debug for RuspAtom {
     }
 }
 
+// Escape `s` with the reader's string syntax, so that a printed string
+// reads back as the same string.
+fn escape_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            '\0' => res.push_str("\\0"),
+            '\x1b' => res.push_str("\\e"),
+            c if c.is_control() => res.push_str(&format!("\\x{:02x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
 impl std::fmt::Display for RuspAtom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             RuspAtom::Int(i) => i.to_string(),
-            RuspAtom::Float(i) => i.to_string(),
-            RuspAtom::String(s) => format!("\"{}\"", s),
+            RuspAtom::BigInt(i) => i.to_string(),
+            RuspAtom::Ratio(r) => r.to_string(),
+            RuspAtom::Float(f) if f.is_nan() => "+nan.0".to_string(),
+            RuspAtom::Float(f) if f.is_infinite() && *f > 0.0 => "+inf.0".to_string(),
+            RuspAtom::Float(f) if f.is_infinite() => "-inf.0".to_string(),
+            // unlike `to_string`, this keeps the point or an exponent, so
+            // that floats read back as floats
+            RuspAtom::Float(f) => format!("{:?}", f),
+            RuspAtom::String(s) => format!("\"{}\"", escape_string(s)),
             RuspAtom::Symbol(s) => s.to_string(),
             RuspAtom::Keyword(s) => s.to_string(),
             RuspAtom::Func(_) => "#<function>".to_string(),
-            RuspAtom::Lambda { params, body } => format!("#<lambda {} {}>", params, body),
+            RuspAtom::Special(_) => "#<special-form>".to_string(),
+            RuspAtom::Lambda { params, body, .. } => {
+                let mut lst = vec![params.to_string()];
+                for x in body.into_iter().flatten() {
+                    lst.push(x.to_string());
+                }
+                format!("#<lambda {}>", lst.join(" "))
+            }
+            RuspAtom::Macro(expander) => format!("#<macro {}>", expander),
         };
         write!(f, "{}", str)
     }
 }
 
+impl std::fmt::Display for RuspSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let object = RuspExp::Cons {
+            car: std::sync::Arc::new(RuspExp::Atom(RuspAtom::Symbol(self.symbol.clone()))),
+            cdr: std::sync::Arc::new(self.data.clone()),
+        };
+        write!(f, "{}", object)
+    }
+}
+
+impl std::fmt::Display for RuspSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
+        }
+    }
+}
+
+// `(quote x)` and the backquote forms print in the short form they are read
+// from.
+fn short_form(exp: &RuspExp) -> Option<String> {
+    if let RuspExp::Cons { car, cdr } = exp {
+        if let (RuspExp::Atom(RuspAtom::Symbol(s)), RuspExp::Cons { car: arg, cdr: end }) =
+            (&**car, &**cdr)
+        {
+            let prefix = match s.as_str() {
+                "quote" => "'",
+                "backquote" => "`",
+                "unquote" => ",",
+                "unquote-splicing" => ",@",
+                _ => return None,
+            };
+            if end.nilp() {
+                return Some(format!("{}{}", prefix, arg));
+            }
+        }
+    }
+    None
+}
+
 impl std::fmt::Display for RuspExp {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let str = match self {
             RuspExp::Atom(atom) => atom.to_string(),
             RuspExp::Cons { car, cdr } => || -> String {
-                if let RuspExp::Atom(RuspAtom::Symbol(s)) = &**car && s == "quote" {
-                    if let RuspExp::Cons { car, cdr } = &**cdr {
-                        if cdr.nilp() {
-                            return format!("'{}", car);
-                        }
-                    }
+                if let Some(s) = short_form(self) {
+                    return s;
                 }
                 let mut lst: Vec<String> = vec![];
                 let mut cell = cdr;
@@ -149,6 +524,10 @@ impl std::fmt::Display for RuspExp {
                     cdr: cell_cdr,
                 } = &**cell
                 {
+                    // a short form in the tail has to stay a dotted pair
+                    if short_form(cell).is_some() {
+                        break;
+                    }
                     lst.push(cell_car.to_string());
                     cell = cell_cdr;
                 }
@@ -164,14 +543,15 @@ impl std::fmt::Display for RuspExp {
     }
 }
 
-impl std::fmt::Display for RuspEnv<'_> {
+impl std::fmt::Display for RuspEnv {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let frame = self.frame();
         let mut var_lst: Vec<String> = vec![];
-        for (k, v) in &self.variable {
+        for (k, v) in &frame.variable {
             var_lst.push(format!("({} {})", k, v));
         }
         let mut func_lst: Vec<String> = vec![];
-        for (k, v) in &self.function {
+        for (k, v) in &frame.function {
             func_lst.push(format!("({} {})", k, v));
         }
         write!(
@@ -208,7 +588,14 @@ impl RuspExp {
     }
 
     pub fn intp(&self) -> bool {
-        matches!(self, RuspExp::Atom(RuspAtom::Int(_)))
+        matches!(
+            self,
+            RuspExp::Atom(RuspAtom::Int(_)) | RuspExp::Atom(RuspAtom::BigInt(_))
+        )
+    }
+
+    pub fn rationalp(&self) -> bool {
+        self.intp() || matches!(self, RuspExp::Atom(RuspAtom::Ratio(_)))
     }
 
     pub fn floatp(&self) -> bool {
@@ -216,7 +603,7 @@ impl RuspExp {
     }
 
     pub fn numberp(&self) -> bool {
-        self.intp() || self.floatp()
+        self.rationalp() || self.floatp()
     }
 
     pub fn stringp(&self) -> bool {
@@ -240,26 +627,124 @@ impl RuspExp {
             cdr: cell_cdr,
         } = cell
         {
-            lst.push(Ok(cell_car));
-            cell = cell_cdr;
+            // cells shared with other values are copied before mutation
+            lst.push(Ok(std::sync::Arc::make_mut(cell_car)));
+            cell = std::sync::Arc::make_mut(cell_cdr);
         }
 
         if !cell.nilp() {
             // dotlist is not allowed
             lst.push(Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
-                expected: "list".into(),
-                actual: cell.to_string().into()
+                expected: "listp".into(),
+                actual: cell.clone()
             })));
         }
         lst.into_iter()
     }
 
+    pub fn as_symbol(&self) -> anyhow::Result<&str> {
+        match self {
+            RuspExp::Atom(RuspAtom::Symbol(s)) => Ok(s),
+            _ => Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
+                expected: "symbolp".into(),
+                actual: self.clone()
+            })),
+        }
+    }
+
+    // An integer value, kept as a fixnum whenever it fits in `i64`.
+    pub fn integer(i: dashu_int::IBig) -> RuspExp {
+        match i64::try_from(&i) {
+            Ok(i) => RuspExp::Atom(RuspAtom::Int(i)),
+            Err(_) => RuspExp::Atom(RuspAtom::BigInt(i)),
+        }
+    }
+
+    pub fn as_bigint(&self) -> Option<dashu_int::IBig> {
+        match self {
+            RuspExp::Atom(RuspAtom::Int(i)) => Some((*i).into()),
+            RuspExp::Atom(RuspAtom::BigInt(i)) => Some(i.clone()),
+            _ => None,
+        }
+    }
+
+    // A rational value, kept as an integer whenever its denominator is 1.
+    pub fn rational(r: dashu_ratio::RBig) -> RuspExp {
+        if r.is_int() {
+            return RuspExp::integer(r.into_parts().0);
+        }
+        RuspExp::Atom(RuspAtom::Ratio(r))
+    }
+
+    pub fn as_rational(&self) -> Option<dashu_ratio::RBig> {
+        match self {
+            RuspExp::Atom(RuspAtom::Ratio(r)) => Some(r.clone()),
+            _ => self.as_bigint().map(dashu_ratio::RBig::from),
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            RuspExp::Atom(RuspAtom::Int(i)) => Some(*i as f64),
+            RuspExp::Atom(RuspAtom::BigInt(i)) => Some(i.to_f64().value()),
+            RuspExp::Atom(RuspAtom::Ratio(r)) => Some(r.to_f64().value()),
+            RuspExp::Atom(RuspAtom::Float(f)) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> anyhow::Result<i64> {
+        match self {
+            RuspExp::Atom(RuspAtom::Int(i)) => Ok(*i),
+            RuspExp::Atom(RuspAtom::BigInt(_)) => {
+                Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
+                    expected: "fixnump".into(),
+                    actual: self.clone()
+                }))
+            }
+            _ => Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
+                expected: "integerp".into(),
+                actual: self.clone()
+            })),
+        }
+    }
+
+    pub fn as_list(&self) -> anyhow::Result<&RuspExp> {
+        if self.listp() {
+            return Ok(self);
+        }
+        Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
+            expected: "listp".into(),
+            actual: self.clone()
+        }))
+    }
+
+    pub fn to_vec(&self) -> anyhow::Result<Vec<RuspExp>> {
+        self.as_list()?
+            .into_iter()
+            .map(|x| Ok((**x?).clone()))
+            .collect()
+    }
+
+    // Identity for atoms that are unique per value; conses, strings and
+    // floats are never `eq` because values here are not shared objects.
+    pub fn eqp(&self, other: &RuspExp) -> bool {
+        match (self, other) {
+            (RuspExp::Atom(RuspAtom::Int(a)), RuspExp::Atom(RuspAtom::Int(b))) => a == b,
+            (RuspExp::Atom(RuspAtom::BigInt(a)), RuspExp::Atom(RuspAtom::BigInt(b))) => a == b,
+            (RuspExp::Atom(RuspAtom::Ratio(a)), RuspExp::Atom(RuspAtom::Ratio(b))) => a == b,
+            (RuspExp::Atom(RuspAtom::Symbol(a)), RuspExp::Atom(RuspAtom::Symbol(b))) => a == b,
+            (RuspExp::Atom(RuspAtom::Keyword(a)), RuspExp::Atom(RuspAtom::Keyword(b))) => a == b,
+            _ => false,
+        }
+    }
+
     pub fn car(&self) -> anyhow::Result<&RuspExp> {
         match self {
             RuspExp::Cons { car, .. } => Ok(car),
             _ => Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
-                expected: "cons".into(),
-                actual: self.to_string().into()
+                expected: "consp".into(),
+                actual: self.clone()
             })),
         }
     }
@@ -268,20 +753,125 @@ impl RuspExp {
         match self {
             RuspExp::Cons { cdr, .. } => Ok(cdr),
             _ => Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
-                expected: "cons".into(),
-                actual: self.to_string().into()
+                expected: "consp".into(),
+                actual: self.clone()
             })),
         }
     }
+
+    // Where the reader found this cons cell, if it came from source text.
+    // Copies of a cell share its car, so they share its span too.
+    pub fn span(&self) -> Option<RuspSpan> {
+        match self {
+            RuspExp::Cons { car, .. } => {
+                let key = std::sync::Arc::as_ptr(car) as usize;
+                SPANS
+                    .lock()
+                    .unwrap()
+                    .spans
+                    .get(&key)
+                    .map(|(_, span)| span.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn set_span(&self, span: RuspSpan) {
+        if let RuspExp::Cons { car, .. } = self {
+            let mut table = SPANS.lock().unwrap();
+            // drop entries for cells that are gone once the table has doubled
+            if table.spans.len() >= 2 * table.live + 1024 {
+                table.spans.retain(|_, (car, _)| car.strong_count() > 0);
+                table.live = table.spans.len();
+            }
+            let key = std::sync::Arc::as_ptr(car) as usize;
+            table
+                .spans
+                .insert(key, (std::sync::Arc::downgrade(car), span));
+        }
+    }
+}
+
+impl Default for RuspEnv {
+    fn default() -> RuspEnv {
+        RuspEnv::new(RuspFrame::default())
+    }
 }
 
-impl RuspEnv<'_> {
-    pub fn get_variable(&self, key: &str) -> anyhow::Result<&RuspExp> {
-        if let Some(val) = self.variable.get(key) {
-            return Ok(val);
+impl RuspEnv {
+    fn frame(&self) -> std::sync::MutexGuard<'_, RuspFrame> {
+        self.0.lock().unwrap()
+    }
+
+    fn new(frame: RuspFrame) -> RuspEnv {
+        let env = RuspEnv(std::sync::Arc::new(std::sync::Mutex::new(frame)));
+        // look for cycles once the table has doubled
+        let collect = FRAMES.with(|x| {
+            let mut table = x.borrow_mut();
+            table.frames.push(std::sync::Arc::downgrade(&env.0));
+            table.frames.len() >= 2 * table.live + 1024
+        });
+        if collect {
+            collect_cycles();
+        }
+        env
+    }
+
+    pub fn with_outer(outer: &RuspEnv) -> RuspEnv {
+        RuspEnv::new(RuspFrame {
+            outer: Some(outer.clone()),
+            ..Default::default()
+        })
+    }
+
+    fn id(&self) -> usize {
+        std::sync::Arc::as_ptr(&self.0) as usize
+    }
+
+    // A handle that does not keep the frame alive.
+    pub fn downgrade(&self) -> std::sync::Weak<std::sync::Mutex<RuspFrame>> {
+        std::sync::Arc::downgrade(&self.0)
+    }
+
+    pub fn global(&self) -> RuspEnv {
+        match &self.frame().outer {
+            Some(env) => env.global(),
+            None => self.clone(),
+        }
+    }
+
+    pub fn define_variable(&self, key: &str, val: RuspExp) {
+        self.frame().variable.insert(key.to_string(), val);
+    }
+
+    // Update the nearest existing binding, or create a global one when
+    // nothing up the chain binds `key`.
+    pub fn set_variable(&self, key: &str, val: RuspExp) {
+        let mut frame = self.frame();
+        if let Some(place) = frame.variable.get_mut(key) {
+            *place = val;
+            return;
+        }
+
+        match &frame.outer {
+            Some(env) => env.set_variable(key, val),
+            None => {
+                frame.variable.insert(key.to_string(), val);
+            }
+        }
+    }
+
+    pub fn define_function(&self, key: &str, val: RuspExp) {
+        self.frame().function.insert(key.to_string(), val);
+    }
+
+    pub fn get_variable(&self, key: &str) -> anyhow::Result<RuspExp> {
+        let frame = self.frame();
+        if let Some(val) = frame.variable.get(key) {
+            return Ok(val.clone());
         }
 
-        if let Some(env) = &self.outer {
+        if let Some(env) = &frame.outer {
             return env.get_variable(key);
         }
 
@@ -290,12 +880,13 @@ impl RuspEnv<'_> {
         }))
     }
 
-    pub fn get_function(&self, key: &str) -> anyhow::Result<&RuspExp> {
-        if let Some(val) = self.function.get(key) {
-            return Ok(val);
+    pub fn get_function(&self, key: &str) -> anyhow::Result<RuspExp> {
+        let frame = self.frame();
+        if let Some(val) = frame.function.get(key) {
+            return Ok(val.clone());
         }
 
-        if let Some(env) = &self.outer {
+        if let Some(env) = &frame.outer {
             return env.get_function(key);
         }
 
@@ -306,42 +897,68 @@ impl RuspEnv<'_> {
 }
 
 macro_rules! extract_args {
-    (@var $var: ident, $args: ident, $args_len: ident, $nil: ident) => {
-        let $var = $args.pop_front().ok_or_else(|| anyhow::anyhow!(types::RuspErr::WrongNumberOfArguments {
-            required: 1,
-            allowed: None,
-            actual: $args_len,
-        }))?;
+    (@var $var: ident, $args: ident, $wrong_args: ident, $nil: ident) => {
+        let $var = $args.pop_front().ok_or_else($wrong_args)?;
     };
-    (@var &optional $var: ident, $args: ident, $args_len: ident, $nil: ident) => {
+    (@var &optional $var: ident, $args: ident, $wrong_args: ident, $nil: ident) => {
         let $var = $args.pop_front().unwrap_or_else(|| &$nil);
     };
+    (@var &rest $var: ident, $args: ident, $wrong_args: ident, $nil: ident) => {
+        let $var = &$args
+            .drain(..)
+            .map(|x| (**x).clone())
+            .collect::<crate::types::RuspExp>();
+    };
+    (@required $var: ident) => { 1 };
+    (@required & $annotation: ident $var: ident) => { 0 };
+    (@allowed &rest $var: ident) => { None };
+    (@allowed $(& $annotation: ident)? $var: ident) => { Some(1) };
     ($arg: ident, $env: ident, _, $body: block) => {{
         $body
     }};
     ($arg: ident, $env: ident, ($($(& $annotation: ident)? $var: ident),+), $body: block) => {{
         let mut args = $arg.into_iter().collect::<Result<std::collections::VecDeque<_>, _>>()?;
         let args_len = args.len();
-        #[allow(unused_variables)]
-        let nil = Box::new(crate::types::nil!());
+        let required: usize = 0 $(+ crate::types::extract_args!(@required $(& $annotation)? $var))+;
+        let mut allowed = Some(0);
         $(
-            crate::types::extract_args!(@var $(& $annotation)? $var, args, args_len, nil);
+            allowed = allowed.and_then(|n: usize| {
+                crate::types::extract_args!(@allowed $(& $annotation)? $var).map(|x: usize| n + x)
+            });
         )+
-        anyhow::ensure!(args.is_empty(), types::RuspErr::WrongNumberOfArguments {
-            required: 1,
-            allowed: None,
+        let wrong_args = || anyhow::anyhow!(types::RuspErr::WrongNumberOfArguments {
+            required,
+            allowed,
             actual: args_len,
         });
+        #[allow(unused_variables)]
+        let nil = std::sync::Arc::new(crate::types::nil!());
+        $(
+            crate::types::extract_args!(@var $(& $annotation)? $var, args, wrong_args, nil);
+        )+
+        anyhow::ensure!(args.is_empty(), wrong_args());
         $body
     }};
 }
 
 pub(crate) use extract_args;
 
+impl FromIterator<RuspExp> for RuspExp {
+    fn from_iter<I: IntoIterator<Item = RuspExp>>(iter: I) -> Self {
+        let lst = iter.into_iter().collect::<Vec<_>>();
+        lst.into_iter()
+            .rev()
+            .fold(nil!(), |cdr, car| RuspExp::Cons {
+                car: std::sync::Arc::new(car),
+                cdr: std::sync::Arc::new(cdr),
+            })
+    }
+}
+
 pub struct ListIter<'a>(&'a RuspExp);
 
 impl<'a> Iterator for ListIter<'a> {
-    type Item = anyhow::Result<&'a Box<RuspExp>>;
+    type Item = anyhow::Result<&'a std::sync::Arc<RuspExp>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let RuspExp::Cons { car, cdr } = self.0 {
@@ -352,8 +969,8 @@ impl<'a> Iterator for ListIter<'a> {
         if !self.0.nilp() {
             // dotlist is not allowed
             return Some(Err(anyhow::anyhow!(RuspErr::WrongTypeArgument {
-                expected: "nil".into(),
-                actual: self.0.to_string().into()
+                expected: "listp".into(),
+                actual: self.0.clone()
             })));
         }
 
@@ -362,7 +979,7 @@ impl<'a> Iterator for ListIter<'a> {
 }
 
 impl<'a> IntoIterator for &'a RuspExp {
-    type Item = anyhow::Result<&'a Box<RuspExp>>;
+    type Item = anyhow::Result<&'a std::sync::Arc<RuspExp>>;
     type IntoIter = ListIter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
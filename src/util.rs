@@ -45,3 +45,50 @@ where
     J: ExactSizeIterator,
 {
 }
+
+// A splitmix64 generator. It is not cryptographically secure, but the same
+// seed always gives the same sequence.
+#[derive(Clone, Debug)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    pub fn from_time() -> Random {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Random::new(now.as_nanos() as u64)
+    }
+
+    // Seed from the bytes of `s` with FNV-1a, which is stable across runs
+    // unlike the standard library hasher.
+    pub fn from_string(s: &str) -> Random {
+        let seed = s.bytes().fold(0xcbf29ce484222325, |acc, b| {
+            (acc ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        Random::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // A uniform integer in `[0, limit)`; draws that would bias the result
+    // towards small values are rejected.
+    pub fn below(&mut self, limit: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % limit;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % limit;
+            }
+        }
+    }
+}